@@ -0,0 +1,72 @@
+//! Compares the indexed overlap queries against a scan over every event.
+//! Run with `cargo bench --bench overlap`
+use calib::{Event, EventCalendar};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 200;
+
+/// a calendar of `n` one hour events spread over a year
+fn calendar(n: i64) -> EventCalendar {
+    let base = NaiveDate::from_ymd_opt(2023, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+
+    let mut cal = EventCalendar::default();
+    for i in 0..n {
+        let start = base + Duration::minutes(i * 60 * 24 * 365 / n);
+        let evt = Event::new(format!("Event {i}"), &start.date())
+            .set_end(start + Duration::hours(1))
+            .unwrap()
+            .set_start(start)
+            .unwrap();
+        cal.add_event(evt);
+    }
+    cal
+}
+
+/// print the average time of `f` over `ITERATIONS` runs
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    let now = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    println!("{name:<32} {:>12.2?}", now.elapsed() / ITERATIONS);
+}
+
+fn main() {
+    let start: NaiveDateTime = NaiveDate::from_ymd_opt(2023, 6, 1)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let end = start + Duration::hours(8);
+    let probe = Event::new("Probe".into(), &start.date())
+        .set_end(end)
+        .unwrap()
+        .set_start(start)
+        .unwrap();
+
+    for n in [1_000, 100_000] {
+        let cal = calendar(n);
+        bench(&format!("events_in_range ({n})"), || {
+            cal.events_in_range(black_box(start), black_box(end))
+                .count()
+        });
+        bench(&format!("scan range ({n})"), || {
+            cal.iter()
+                .filter(|evt| {
+                    (evt.start() >= start && evt.start() <= end)
+                        || (evt.end() >= start && evt.end() <= end)
+                })
+                .count()
+        });
+        bench(&format!("conflicts ({n})"), || {
+            cal.conflicts(black_box(&probe)).len()
+        });
+        bench(&format!("scan conflicts ({n})"), || {
+            cal.iter().filter(|evt| evt.overlaps(&probe)).count()
+        });
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use uuid::Uuid;
+
+use super::Event;
+
+/// Storage for the events of an [`EventCalendar`](crate::EventCalendar),
+/// keyed by id, e.g. in memory ([`MemoryStore`]) or mirrored to a database.
+///
+/// The calendar shares each event with the store and keeps its own
+/// chronological and interval indexes next to it, so every event is held in
+/// memory whatever the store and calendar queries never go through
+/// [`EventStore::range`]. A store is therefore a place to persist or mirror
+/// the calendar's events rather than a way to page them in lazily. `range`
+/// is part of the contract so a store can still be queried on its own, e.g.
+/// to read a single month from a database without building a calendar
+pub trait EventStore {
+    /// add an event, returning the event with the same id it replaced
+    fn insert(&mut self, evt: Rc<Event>) -> Option<Rc<Event>>;
+
+    /// remove the event with the given id, returning it if it existed
+    fn remove(&mut self, id: &Uuid) -> Option<Rc<Event>>;
+
+    /// return the event with the given id
+    fn get(&self, id: &Uuid) -> Option<&Rc<Event>>;
+
+    /// return the events that share any time with `start..=end`, ordered by
+    /// start time. The default scans [`EventStore::events`], stores with an
+    /// index on time should override it
+    fn range(&self, start: NaiveDateTime, end: NaiveDateTime) -> Vec<Rc<Event>> {
+        let mut evts: Vec<Rc<Event>> = self
+            .events()
+            .filter(|evt| evt.start() <= end && evt.end() >= start)
+            .cloned()
+            .collect();
+        evts.sort();
+        evts
+    }
+
+    /// iterate over all events in the store, in any order
+    fn events(&self) -> Box<dyn Iterator<Item = &Rc<Event>> + '_>;
+
+    /// return the number of events in the store
+    fn len(&self) -> usize;
+
+    /// returns true if the store holds no events
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// remove all events from the store
+    fn clear(&mut self) {
+        let ids: Vec<Uuid> = self.events().map(|evt| *evt.id()).collect();
+        for id in ids {
+            self.remove(&id);
+        }
+    }
+
+    /// release memory left behind by removals, does nothing by default
+    fn shrink(&mut self) {}
+}
+
+/// The default in-memory [`EventStore`], a map from id to event
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    events: BTreeMap<Uuid, Rc<Event>>,
+}
+
+impl MemoryStore {
+    /// raw access to the map, letting tests put a calendar out of sync
+    #[cfg(test)]
+    pub(crate) fn map_mut(&mut self) -> &mut BTreeMap<Uuid, Rc<Event>> {
+        &mut self.events
+    }
+}
+
+impl EventStore for MemoryStore {
+    fn insert(&mut self, evt: Rc<Event>) -> Option<Rc<Event>> {
+        self.events.insert(*evt.id(), evt)
+    }
+
+    fn remove(&mut self, id: &Uuid) -> Option<Rc<Event>> {
+        self.events.remove(id)
+    }
+
+    fn get(&self, id: &Uuid) -> Option<&Rc<Event>> {
+        self.events.get(id)
+    }
+
+    fn events(&self) -> Box<dyn Iterator<Item = &Rc<Event>> + '_> {
+        Box::new(self.events.values())
+    }
+
+    fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    fn shrink(&mut self) {
+        self.events = std::mem::take(&mut self.events).into_iter().collect();
+    }
+}
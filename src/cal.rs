@@ -1,50 +1,1466 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 use uuid::Uuid;
 
-use super::{event::Event, IntoUuid};
+use super::{
+    backend::{EventStore, MemoryStore},
+    csv, day_end, day_start,
+    event::{add_saturating, Event, EventPatch, EventSummary, OPEN_END},
+    ics,
+    interval::IntervalIndex,
+    Clock, EventError, IcsError, ImportError, IntoUuid, LoadError,
+};
 
 // Maybe use a BTreeSet to keep events in chronological order
 // and then add a second field which is a Hashmap<UUID, &Event>
 // keep the BTreeSet as append-only and only edit events through
 // dereferencing hashmap
 
-/// Represents a calendar of events
-#[derive(Default)]
-pub struct EventCalendar {
-    ids: BTreeMap<Uuid, Rc<Event>>,
+/// The differences between two calendars, see [`EventCalendar::diff`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CalendarDiff {
+    /// ids of events only found in the other calendar
+    pub added: Vec<Uuid>,
+    /// ids of events only found in this calendar
+    pub removed: Vec<Uuid>,
+    /// ids of events found in both calendars but with different content
+    pub changed: Vec<Uuid>,
+}
+
+/// How [`EventCalendar::import`] resolves events whose id already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportPolicy {
+    /// keep the existing event and ignore the imported one
+    SkipExisting,
+    /// replace the existing event with the imported one
+    OverwriteExisting,
+    /// keep whichever event was modified most recently, existing events
+    /// win ties
+    KeepNewerByModified,
+}
+
+/// A boundary of an event returned by [`EventCalendar::timeline`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelinePoint {
+    Starts(Uuid),
+    Ends(Uuid),
+}
+
+/// An event with its times converted to a display time zone, returned by
+/// [`EventCalendar::events_in_range_tz`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventInTz<'a> {
+    pub event: &'a Event,
+    pub start: DateTime<Tz>,
+    /// None for open-ended events
+    pub end: Option<DateTime<Tz>>,
+}
+
+/// The part of each day in which [`EventCalendar::schedule`] may place
+/// events, `start` must be before `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl WorkingHours {
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        Self { start, end }
+    }
+
+    /// the earliest time at or after `at` inside working hours
+    fn next_open(&self, at: NaiveDateTime) -> Option<NaiveDateTime> {
+        if at.time() < self.start {
+            Some(at.date().and_time(self.start))
+        } else if at.time() < self.end {
+            Some(at)
+        } else {
+            at.date().succ_opt().map(|date| date.and_time(self.start))
+        }
+    }
+}
+
+/// Settings for an [`EventCalendar`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CalendarConfig {
+    /// length of events created with [`EventCalendar::new_event`], all day
+    /// events are created if None
+    pub default_duration: Option<Duration>,
+}
+
+/// the range of the interval index that can hold events conflicting with
+/// `candidate`. All day events cover the second after [`day_end`] too, see
+/// [`Event::overlaps`], so the range is widened by that second on each side
+fn conflict_window(candidate: &Event, buffer: Duration) -> (NaiveDateTime, NaiveDateTime) {
+    let buffer = buffer + Duration::seconds(1);
+    (
+        add_saturating(candidate.start(), -buffer),
+        add_saturating(candidate.end(), buffer),
+    )
+}
+
+/// the UID of the occurrence of `master` starting at `start`, distinct for
+/// each occurrence and the same every time it is exported
+fn occurrence_uid(master: &Uuid, start: NaiveDateTime) -> Uuid {
+    let (high, low) = master.as_u64_pair();
+    Uuid::from_u64_pair(high, low ^ start.and_utc().timestamp() as u64)
+}
+
+/// The JSON written by [`EventCalendar::save`], a plain array of events
+/// unless some are archived, so calendars saved before archiving was
+/// persisted still load
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SavedCalendar<E> {
+    Events(Vec<E>),
+    WithArchive { events: Vec<E>, archived: Vec<E> },
+}
+
+/// wrap VEVENT blocks in a VCALENDAR
+fn ics_document(vevents: impl Iterator<Item = String>) -> String {
+    let mut ics = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//calib//EN\r\n");
+    for vevent in vevents {
+        ics.push_str(&vevent);
+    }
+    ics.push_str("END:VCALENDAR\r\n");
+    ics
+}
+
+/// Mutable access to an event yielded by [`EventCalendar::iter_mut`], the
+/// event is taken out of the calendar while the guard is alive and put
+/// back, re-indexed, when the guard is dropped
+pub struct EventGuard<'a, S: EventStore = MemoryStore> {
+    evt: Option<Event>,
+    cal: Rc<RefCell<&'a mut EventCalendar<S>>>,
+}
+
+impl<S: EventStore> std::ops::Deref for EventGuard<'_, S> {
+    type Target = Event;
+
+    fn deref(&self) -> &Event {
+        // only taken in drop
+        self.evt.as_ref().unwrap()
+    }
+}
+
+impl<S: EventStore> std::ops::DerefMut for EventGuard<'_, S> {
+    fn deref_mut(&mut self) -> &mut Event {
+        self.evt.as_mut().unwrap()
+    }
+}
+
+impl<S: EventStore> Drop for EventGuard<'_, S> {
+    fn drop(&mut self) {
+        if let Some(evt) = self.evt.take() {
+            self.cal.borrow_mut().add_event(evt);
+        }
+    }
+}
+
+/// Represents a calendar of events, held in an [`EventStore`] that is kept
+/// in memory by default
+pub struct EventCalendar<S: EventStore = MemoryStore> {
+    ids: S,
     evts: BTreeSet<Rc<Event>>,
+    // secondary index ordered strictly by start time, then id
+    by_start: BTreeSet<(NaiveDateTime, Uuid)>,
+    // interval tree for overlap queries
+    intervals: IntervalIndex,
+    archived: BTreeMap<Uuid, Event>,
+    config: CalendarConfig,
+}
+
+// only implemented for the in-memory store, so that
+// `EventCalendar::default()` needs no type annotations
+impl Default for EventCalendar {
+    fn default() -> Self {
+        Self::with_store(MemoryStore::default())
+    }
 }
 
 impl EventCalendar {
+    /// create an empty calendar with the given settings
+    pub fn with_config(config: CalendarConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// read every VEVENT of an iCalendar (.ics) document, other components
+    /// such as VTIMEZONE are skipped. Folded lines are joined first, so long
+    /// values split across several lines are read back whole. A VEVENT with
+    /// a RECURRENCE-ID overrides that occurrence of the recurring event with
+    /// its UID
+    pub fn from_ics(text: &str) -> Result<EventCalendar, IcsError> {
+        let mut evts: Vec<Event> = Vec::new();
+        let mut overrides = Vec::new();
+        let mut block: Option<Vec<String>> = None;
+
+        for line in ics::unfold_lines(text) {
+            match &mut block {
+                None if line == "BEGIN:VEVENT" => block = Some(vec![line]),
+                None => {}
+                Some(lines) => {
+                    let done = line == "END:VEVENT";
+                    lines.push(line);
+                    if done {
+                        let lines = block.take().unwrap();
+                        match Event::from_ics_component(&lines.join("\r\n"))? {
+                            (evt, Some(date)) => overrides.push((date, evt)),
+                            (evt, None) => evts.push(evt),
+                        }
+                    }
+                }
+            }
+        }
+
+        if block.is_some() {
+            return Err(IcsError::NotAnEvent);
+        }
+
+        // overrides whose recurring event is missing are kept as events
+        for (date, occ) in overrides {
+            match evts.iter_mut().find(|evt| evt.id() == occ.id()) {
+                Some(master) => master.attach_ics_override(date, occ),
+                None => evts.push(occ),
+            }
+        }
+
+        let mut cal = EventCalendar::default();
+        for evt in evts {
+            cal.add_event(evt);
+        }
+        Ok(cal)
+    }
+
+    /// read a calendar previously written with [`EventCalendar::save`],
+    /// including its archived events
+    pub fn load<R: std::io::Read>(r: R) -> Result<EventCalendar, LoadError> {
+        let (evts, archived): (Vec<Event>, _) = match serde_json::from_reader(r)? {
+            SavedCalendar::Events(evts) => (evts, Vec::new()),
+            SavedCalendar::WithArchive { events, archived } => (events, archived),
+        };
+
+        if let Some(evt) = evts.iter().chain(&archived).find(|evt| !evt.is_valid()) {
+            return Err(LoadError::InvalidEvent(*evt.id()));
+        }
+
+        let mut cal = EventCalendar::default();
+        for evt in evts {
+            cal.add_event(evt);
+        }
+        cal.archived = archived.into_iter().map(|evt| (*evt.id(), evt)).collect();
+
+        Ok(cal)
+    }
+
+    /// decode a calendar previously encoded with [`EventCalendar::to_bytes`],
+    /// including its archived events
+    #[cfg(feature = "binary")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<EventCalendar, crate::DecodeError> {
+        use crate::event::EventRecord;
+
+        let records: Vec<EventRecord> = bincode::deserialize(bytes)?;
+        // archived events follow the active ones, older encodings end here
+        let rest = &bytes[bincode::serialized_size(&records)? as usize..];
+        let archived: Vec<EventRecord> = match rest.is_empty() {
+            true => Vec::new(),
+            false => bincode::deserialize(rest)?,
+        };
+        let evts: Vec<Event> = records.into_iter().map(Event::from).collect();
+        let archived: Vec<Event> = archived.into_iter().map(Event::from).collect();
+
+        if let Some(evt) = evts.iter().chain(&archived).find(|evt| !evt.is_valid()) {
+            return Err(crate::DecodeError::InvalidEvent(*evt.id()));
+        }
+
+        let mut cal = EventCalendar::default();
+        for evt in evts {
+            cal.add_event(evt);
+        }
+        cal.archived = archived.into_iter().map(|evt| (*evt.id(), evt)).collect();
+
+        Ok(cal)
+    }
+
+    /// raw access to the id index, letting tests put the calendar out of sync
+    #[cfg(test)]
+    pub(crate) fn ids_mut(&mut self) -> &mut BTreeMap<Uuid, Rc<Event>> {
+        self.ids.map_mut()
+    }
+}
+
+impl<S: EventStore> EventCalendar<S> {
+    /// create a calendar backed by `store`, indexing any events it
+    /// already holds
+    pub fn with_store(store: S) -> Self {
+        let mut cal = Self {
+            ids: store,
+            evts: BTreeSet::new(),
+            by_start: BTreeSet::new(),
+            intervals: IntervalIndex::default(),
+            archived: BTreeMap::new(),
+            config: CalendarConfig::default(),
+        };
+        cal.reindex();
+        cal
+    }
+
+    /// returns the store holding the events
+    pub fn store(&self) -> &S {
+        &self.ids
+    }
+
+    /// returns the settings of the calendar
+    pub fn config(&self) -> &CalendarConfig {
+        &self.config
+    }
+
+    /// create an event named `name` starting at `start` and lasting the
+    /// configured default duration, or an all day event on the start date
+    /// if there is none, returning its id
+    pub fn new_event(&mut self, name: String, start: NaiveDateTime) -> Result<Uuid, EventError> {
+        let evt = match self.config.default_duration {
+            Some(duration) if duration <= Duration::zero() => {
+                return Err(EventError::InvalidDuration)
+            }
+            Some(duration) => Event::new(name, &start.date())
+                .set_end(start + duration)?
+                .set_start(start)?,
+            None => Event::new(name, &start.date()),
+        };
+
+        let id = *evt.id();
+        self.add_event(evt);
+        Ok(id)
+    }
+
     /// inserts event into calednar, returning true if the event
     /// is new to the calendar and false if the event already exits
     pub fn add_event(&mut self, event: Event) -> bool {
-        let id = *event.id();
-        let evt = Rc::new(event);
-        self.ids.insert(id, Rc::clone(&evt));
-        self.evts.insert(Rc::clone(&evt))
+        self.insert_rc(Rc::new(event))
+    }
+
+    /// insert `event`, replacing any event with the same id and returning
+    /// the replaced event
+    pub fn set_event(&mut self, event: Event) -> Option<Event> {
+        let old = self.remove_by_id(event.id());
+        self.add_event(event);
+        old.map(|evt| Rc::try_unwrap(evt).unwrap_or_else(|evt| Event::clone(&evt)))
     }
 
-    /// return an iterator of all events between start and end
+    /// insert an event into all indexes, see [`EventCalendar::add_event`]
+    fn insert_rc(&mut self, evt: Rc<Event>) -> bool {
+        let id = *evt.id();
+        let old = self.ids.insert(Rc::clone(&evt));
+        if let Some(old) = &old {
+            self.evts.remove(old);
+            self.by_start.remove(&(old.start(), id));
+            self.intervals.remove(old.start(), id);
+        }
+        self.by_start.insert((evt.start(), id));
+        self.intervals.insert(evt.start(), evt.end(), id);
+        self.evts.insert(evt);
+        old.is_none()
+    }
+
+    /// remove an event from the calendar, returning it if it existed
+    pub fn remove_event<T: IntoUuid>(&mut self, id: T) -> Option<Event> {
+        self.remove_by_id(&id.into_uuid())
+            .map(|evt| Rc::try_unwrap(evt).unwrap_or_else(|evt| Event::clone(&evt)))
+    }
+
+    /// remove all active events from the calendar, returning them in
+    /// chronological order. Archived events are left untouched
+    pub fn drain(&mut self) -> Vec<Event> {
+        self.ids.clear();
+        self.by_start.clear();
+        self.intervals.clear();
+        std::mem::take(&mut self.evts)
+            .into_iter()
+            .map(|evt| Rc::try_unwrap(evt).unwrap_or_else(|evt| Event::clone(&evt)))
+            .collect()
+    }
+
+    /// remove an event from all indexes, returning it if it existed
+    fn remove_by_id(&mut self, id: &Uuid) -> Option<Rc<Event>> {
+        let evt = self.ids.remove(id)?;
+        self.evts.remove(&evt);
+        self.by_start.remove(&(evt.start(), *id));
+        self.intervals.remove(evt.start(), *id);
+        Some(evt)
+    }
+
+    /// shift each event by its duration, returning the ids and new start
+    /// times. The ids must belong to events in the calendar
+    fn shift_events(&mut self, moves: Vec<(Uuid, Duration)>) -> Vec<(Uuid, NaiveDateTime)> {
+        moves
+            .into_iter()
+            .map(|(id, by)| {
+                let evt = self.remove_by_id(&id).unwrap();
+                let evt = Event::clone(&evt).shift(by);
+                let start = evt.start();
+                self.add_event(evt);
+                (id, start)
+            })
+            .collect()
+    }
+
+    /// move all events from `other` into this calendar, using `policy` to
+    /// decide what happens when an event with the same id already exists
+    pub fn import<T: EventStore>(&mut self, other: EventCalendar<T>, policy: ImportPolicy) {
+        for evt in other.ids.events() {
+            let id = *evt.id();
+            let replace = match (self.ids.get(&id), policy) {
+                (None, _) => true,
+                (Some(_), ImportPolicy::SkipExisting) => false,
+                (Some(_), ImportPolicy::OverwriteExisting) => true,
+                (Some(existing), ImportPolicy::KeepNewerByModified) => {
+                    evt.modified() > existing.modified()
+                }
+            };
+
+            if replace {
+                self.remove_by_id(&id);
+                self.insert_rc(Rc::clone(evt));
+            }
+        }
+    }
+
+    /// read events from `r` and add them to the calendar, telling JSON (as
+    /// written by [`EventCalendar::save`], or a single event), iCalendar and
+    /// CSV input apart by their first line. CSV input needs a header row with
+    /// name, start and end columns. Events with ids already in the calendar
+    /// replace the existing ones, and the number of events that were not in
+    /// the calendar before is returned.
+    /// Nothing is added if any part of the input is invalid
+    pub fn import_auto<R: std::io::Read>(&mut self, mut r: R) -> Result<usize, ImportError> {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        let first_line = text.lines().next().unwrap_or_default().trim_end();
+
+        let evts: Vec<Event> = if first_line.starts_with("BEGIN:VCALENDAR")
+            || first_line.starts_with("BEGIN:VEVENT")
+        {
+            EventCalendar::from_ics(text)?.drain()
+        } else if text.starts_with('[') {
+            EventCalendar::load(text.as_bytes())?.drain()
+        } else if text.starts_with('{') {
+            let evt: Event = serde_json::from_str(text).map_err(LoadError::from)?;
+            if !evt.is_valid() {
+                return Err(LoadError::InvalidEvent(*evt.id()).into());
+            }
+            vec![evt]
+        } else if csv::is_header(first_line) {
+            csv::parse_events(text)?
+        } else {
+            return Err(ImportError::UnknownFormat);
+        };
+
+        let mut added = 0;
+        for evt in evts {
+            if self.set_event(evt).is_none() {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// apply `patch` to the event with the given id, the calendar is left
+    /// unchanged if the event does not exist or the patch is invalid
+    pub fn patch_event<T: IntoUuid>(&mut self, id: T, patch: EventPatch) -> Result<(), EventError> {
+        let id = id.into_uuid();
+        let current = self.ids.get(&id).ok_or(EventError::NotFound(id))?;
+
+        // patch a copy so nothing changes if the patch fails
+        let patched = Event::clone(current).apply_patch(patch)?;
+
+        self.remove_by_id(&id);
+        self.add_event(patched);
+        Ok(())
+    }
+
+    /// return the first event (in chronological order) for which `matches`
+    /// returns true, or insert the event created by `make` if there is none
+    pub fn get_or_create<F: FnOnce() -> Event>(
+        &mut self,
+        matches: impl Fn(&Event) -> bool,
+        make: F,
+    ) -> &Event {
+        let id = match self.evts.iter().find(|evt| matches(evt)) {
+            Some(evt) => *evt.id(),
+            None => {
+                let evt = make();
+                let id = *evt.id();
+                self.add_event(evt);
+                id
+            }
+        };
+
+        self.indexed(&id)
+    }
+
+    /// return an iterator of all events between start and end. Open-ended
+    /// events extend forever, so they are included once they have started
     pub fn events_in_range(
         &self,
         start: NaiveDateTime,
         end: NaiveDateTime,
     ) -> impl Iterator<Item = &Rc<Event>> {
-        self.evts.iter().filter(move |evt| {
-            (evt.start() >= start && evt.start() <= end) || (evt.end() >= start && evt.end() <= end)
+        let mut evts: Vec<&Rc<Event>> = self
+            .overlapping(start, end)
+            .filter(|evt| {
+                (evt.start() >= start && evt.start() <= end)
+                    || (evt.end() >= start && evt.end() <= end)
+                    || (evt.is_open_ended() && evt.start() <= end)
+            })
+            .collect();
+        evts.sort();
+        evts.into_iter()
+    }
+
+    /// return an iterator of all events that end between start and end,
+    /// ordered by end time. Unlike [`EventCalendar::events_in_range`] an
+    /// event that only starts in the window is not included
+    pub fn events_in_range_by_end(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> impl Iterator<Item = &Rc<Event>> {
+        let mut evts: Vec<&Rc<Event>> = self
+            .overlapping(start, end)
+            .filter(|evt| evt.end() >= start && evt.end() <= end)
+            .collect();
+        evts.sort_by(|a, b| a.end().cmp(&b.end()).then_with(|| a.cmp(b)));
+        evts.into_iter()
+    }
+
+    /// call `f` on each event between start and end in chronological order,
+    /// the same events as [`EventCalendar::events_in_range`] but without
+    /// allocating. `f` returns true to continue and false to stop early
+    pub fn for_each_in_range<F: FnMut(&Event) -> bool>(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        f: F,
+    ) {
+        self.iter()
+            .take_while(|evt| evt.start() <= end)
+            .filter(|evt| {
+                (evt.start() >= start && evt.start() <= end)
+                    || (evt.end() >= start && evt.end() <= end)
+                    || evt.is_open_ended()
+            })
+            .all(f);
+    }
+
+    /// return an iterator of all events between the start of `start` and
+    /// the end of `end`, see [`EventCalendar::events_in_range`]
+    pub fn events_between_dates(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> impl Iterator<Item = &Event> {
+        self.events_in_range(start.and_time(day_start()), end.and_time(day_end()))
+            .map(Rc::as_ref)
+    }
+
+    /// return every event start and end between start and end (inclusive)
+    /// sorted by time. At the same time ends come before starts, so the
+    /// number of ongoing events never counts touching events as overlapping
+    pub fn timeline(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Vec<(NaiveDateTime, TimelinePoint)> {
+        let in_window = |time: &NaiveDateTime| *time >= start && *time <= end;
+        let mut points: Vec<(NaiveDateTime, TimelinePoint)> = self
+            .overlapping(start, end)
+            .flat_map(|evt| {
+                let id = *evt.id();
+                [
+                    (evt.start(), TimelinePoint::Starts(id)),
+                    (evt.end(), TimelinePoint::Ends(id)),
+                ]
+            })
+            .filter(|(time, _)| in_window(time))
+            .collect();
+
+        points.sort_by_key(|(time, point)| match point {
+            TimelinePoint::Ends(id) => (*time, 0, *id),
+            TimelinePoint::Starts(id) => (*time, 1, *id),
+        });
+        points
+    }
+
+    /// events sharing at least one instant with the closed range from start
+    /// to end, in no particular order
+    fn overlapping(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> impl Iterator<Item = &Rc<Event>> {
+        self.intervals
+            .overlapping(start, end)
+            .into_iter()
+            .map(|id| self.indexed(&id))
+    }
+
+    /// collect all events between start and end into a vector, in the same
+    /// order as [`EventCalendar::events_in_range`]
+    pub fn events_in_range_vec(&self, start: NaiveDateTime, end: NaiveDateTime) -> Vec<&Event> {
+        self.events_in_range(start, end).map(Rc::as_ref).collect()
+    }
+
+    /// summarize all events between start and end, in the same order as
+    /// [`EventCalendar::events_in_range`]
+    pub fn summaries_in_range(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Vec<EventSummary> {
+        self.events_in_range(start, end)
+            .map(|evt| evt.summary())
+            .collect()
+    }
+
+    /// return an iterator of all events between start and end for which
+    /// `pred` returns true
+    pub fn events_in_range_where<F: Fn(&Event) -> bool>(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        pred: F,
+    ) -> impl Iterator<Item = &Event> {
+        self.events_in_range(start, end)
+            .map(|evt| evt.as_ref())
+            .filter(move |evt| pred(evt))
+    }
+
+    /// return an iterator of all events between start and end other than
+    /// `exclude`, e.g. to check an event being edited against the rest
+    pub fn events_in_range_excluding<T: IntoUuid>(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        exclude: T,
+    ) -> impl Iterator<Item = &Event> {
+        let exclude = exclude.into_uuid();
+        self.events_in_range_where(start, end, move |evt| *evt.id() != exclude)
+    }
+
+    /// return all events sharing any time with the window from start to end,
+    /// comparing absolute instants so events in different zones are matched
+    /// correctly, with their times converted to `display_tz`. Floating
+    /// events without a zone or offset are read as times in `display_tz`.
+    /// Events are ordered by their start instant
+    pub fn events_in_range_tz(
+        &self,
+        start: DateTime<Tz>,
+        end: DateTime<Tz>,
+        display_tz: Tz,
+    ) -> Vec<EventInTz<'_>> {
+        let (start, end) = (start.with_timezone(&Utc), end.with_timezone(&Utc));
+        // local times are never more than a day from UTC
+        let margin = Duration::days(2);
+        let candidates = self.overlapping(
+            add_saturating(start.naive_utc(), -margin),
+            add_saturating(end.naive_utc(), margin),
+        );
+
+        let mut evts: Vec<(DateTime<Utc>, EventInTz)> = candidates
+            .filter_map(|evt| {
+                let (evt_start, evt_end) = evt.to_utc_or(&display_tz);
+                (evt_start < end && evt_end > start).then(|| {
+                    let in_tz = EventInTz {
+                        event: evt,
+                        start: evt_start.with_timezone(&display_tz),
+                        end: (!evt.is_open_ended()).then(|| evt_end.with_timezone(&display_tz)),
+                    };
+                    (evt_start, in_tz)
+                })
+            })
+            .collect();
+        evts.sort_by(|(a, a_tz), (b, b_tz)| a.cmp(b).then_with(|| a_tz.event.cmp(b_tz.event)));
+        evts.into_iter().map(|(_, in_tz)| in_tz).collect()
+    }
+
+    /// return a new calendar with copies of the events sharing any time with
+    /// the window from start to end, keeping their ids and this calendar's
+    /// config. Changes to either calendar do not affect the other
+    pub fn clone_range(&self, start: NaiveDateTime, end: NaiveDateTime) -> EventCalendar {
+        let mut cal = EventCalendar::with_config(self.config);
+        for evt in self.overlapping(start, end) {
+            if evt.start() < end && evt.end() > start {
+                cal.add_event(Event::clone(evt));
+            }
+        }
+        cal
+    }
+
+    /// return an iterator of all events that share any time with the daily
+    /// window from `from` to `to` on some date, e.g. all morning events. A
+    /// window with `to` before `from` wraps past midnight
+    pub fn events_at_time_of_day(
+        &self,
+        from: NaiveTime,
+        to: NaiveTime,
+    ) -> impl Iterator<Item = &Event> {
+        self.iter().filter(move |evt| {
+            let first = evt.start().date().pred_opt().unwrap_or(NaiveDate::MIN);
+            first
+                .iter_days()
+                .take_while(|date| *date <= evt.end().date())
+                .any(|date| {
+                    let start = date.and_time(from);
+                    let end = if to > from {
+                        date.and_time(to)
+                    } else {
+                        date.succ_opt()
+                            .map_or(NaiveDateTime::MAX, |next| next.and_time(to))
+                    };
+                    evt.start() < end && start < evt.end()
+                })
+        })
+    }
+
+    /// return an iterator of all events taking place at the current time
+    /// according to `clock`
+    pub fn ongoing_events<C: Clock>(&self, clock: &C) -> impl Iterator<Item = &Rc<Event>> {
+        let now = clock.now();
+        self.evts
+            .iter()
+            .take_while(move |evt| evt.start() <= now)
+            .filter(move |evt| evt.end() >= now)
+    }
+
+    /// return an iterator over all active events in chronological order.
+    ///
+    /// Events are ordered like [`Event`]'s `Ord`: by start, then end, then
+    /// name and finally id. Use [`EventCalendar::iter_by_start`] for an order
+    /// that only depends on the start time
+    pub fn iter(&self) -> impl Iterator<Item = &Event> {
+        self.evts.iter().map(Rc::as_ref)
+    }
+
+    /// return up to `n` events starting after `after`, ordered by start time
+    /// as in [`EventCalendar::iter_by_start`]
+    pub fn next_events(&self, after: NaiveDateTime, n: usize) -> Vec<&Event> {
+        self.starts_after(after)
+            .take(n)
+            .map(|(_, id)| self.indexed(id).as_ref())
+            .collect()
+    }
+
+    /// return the first event starting after `after`, see
+    /// [`EventCalendar::next_events`]
+    pub fn next_event_after(&self, after: NaiveDateTime) -> Option<&Event> {
+        self.peek_next_after(after)
+            .map(|(id, _)| self.indexed(&id).as_ref())
+    }
+
+    /// return the id and start time of the event
+    /// [`EventCalendar::next_event_after`] would return
+    pub fn peek_next_after(&self, after: NaiveDateTime) -> Option<(Uuid, NaiveDateTime)> {
+        self.starts_after(after)
+            .next()
+            .map(|(start, id)| (*id, *start))
+    }
+
+    /// entries of the start time index strictly after `after`
+    fn starts_after(&self, after: NaiveDateTime) -> impl Iterator<Item = &(NaiveDateTime, Uuid)> {
+        use std::ops::Bound::{Excluded, Unbounded};
+
+        self.by_start
+            .range((Excluded((after, Uuid::max())), Unbounded))
+    }
+
+    /// return an iterator of guards that allow editing each event in
+    /// chronological order, see [`EventGuard`]. Events are visited in their
+    /// order before any edits, and edits to their times are reflected in
+    /// the calendar once each guard is dropped
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = EventGuard<'_, S>> {
+        let ids: Vec<Uuid> = self.evts.iter().map(|evt| *evt.id()).collect();
+        let cal = Rc::new(RefCell::new(self));
+
+        ids.into_iter().filter_map(move |id| {
+            let evt = cal.borrow_mut().remove_event(id)?;
+            Some(EventGuard {
+                evt: Some(evt),
+                cal: Rc::clone(&cal),
+            })
         })
     }
 
+    /// return an iterator over all active events ordered strictly by start
+    /// time, events with the same start are ordered by id (not by end time or
+    /// name as with [`EventCalendar::iter`])
+    pub fn iter_by_start(&self) -> impl Iterator<Item = &Event> {
+        self.by_start
+            .iter()
+            .map(|(_, id)| self.indexed(id).as_ref())
+    }
+
+    /// move an event out of the active calendar without deleting it, it will
+    /// no longer show up in any queries. Returns false if no active event
+    /// has the given id
+    pub fn archive_event<T: IntoUuid>(&mut self, id: T) -> bool {
+        let id = id.into_uuid();
+        match self.remove_event(id) {
+            Some(evt) => {
+                self.archived.insert(id, evt);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// restore an archived event to the active calendar, returns false
+    /// if no archived event has the given id
+    pub fn unarchive_event<T: IntoUuid>(&mut self, id: T) -> bool {
+        match self.archived.remove(&id.into_uuid()) {
+            Some(evt) => {
+                self.add_event(evt);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// return an iterator over all archived events ordered by id
+    pub fn archived_events(&self) -> impl Iterator<Item = &Event> {
+        self.archived.values()
+    }
+
     /// return the first event in the Calendar
     pub fn first_event(&self) -> Option<&Rc<Event>> {
         self.evts.first()
     }
 
+    /// return all events ordered by `cmp` instead of by start time, events
+    /// that compare equal keep their chronological order
+    pub fn sorted_by<F: FnMut(&&Event, &&Event) -> Ordering>(&self, cmp: F) -> Vec<&Event> {
+        let mut evts: Vec<&Event> = self.iter().collect();
+        evts.sort_by(cmp);
+        evts
+    }
+
+    /// return the n-th event (starting at 0) in chronological order
+    pub fn nth_event(&self, n: usize) -> Option<&Event> {
+        self.iter().nth(n)
+    }
+
     /// return a reference to an event from it's ID
     pub fn get<T: IntoUuid>(&self, id: T) -> Option<&Rc<Event>> {
         self.ids.get(&id.into_uuid())
     }
+
+    /// compare this calendar against `other`, reporting which events
+    /// would have to be added, removed or changed to turn this calendar into
+    /// `other`. Events are matched by id and all id lists are sorted
+    pub fn diff<T: EventStore>(&self, other: &EventCalendar<T>) -> CalendarDiff {
+        let mut diff = CalendarDiff::default();
+
+        for evt in self.ids.events() {
+            let id = *evt.id();
+            match other.ids.get(&id) {
+                Some(other_evt) if other_evt != evt => diff.changed.push(id),
+                Some(_) => {}
+                None => diff.removed.push(id),
+            }
+        }
+
+        diff.added = other
+            .ids
+            .events()
+            .map(|evt| *evt.id())
+            .filter(|id| self.ids.get(id).is_none())
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff
+    }
+
+    /// export the calendar as an iCalendar (.ics) document, events are
+    /// written in chronological order. Recurring events are written with an
+    /// RRULE (FREQ of DAILY, WEEKLY or MONTHLY with INTERVAL, BYDAY, COUNT
+    /// and UNTIL) followed by their overridden occurrences, each with a
+    /// RECURRENCE-ID
+    pub fn to_ics(&self) -> String {
+        self.export_ics_where(|_| true)
+    }
+
+    /// export only the events for which `pred` returns true as an
+    /// iCalendar (.ics) document, in the same form as
+    /// [`EventCalendar::to_ics`]. Recurring events keep their RRULE and
+    /// overrides, `pred` is given the recurring event itself
+    pub fn export_ics_where<F: Fn(&Event) -> bool>(&self, pred: F) -> String {
+        ics_document(
+            self.iter()
+                .filter(|evt| pred(evt))
+                .flat_map(|evt| std::iter::once(evt.to_ics_vevent()).chain(evt.to_ics_overrides())),
+        )
+    }
+
+    /// export only the events overlapping the window from start to end as an
+    /// iCalendar (.ics) document. Recurring events are expanded into their
+    /// occurrences within the window. Each occurrence is written as an event
+    /// of its own, with a UID of its own and a RELATED-TO pointing at the
+    /// recurring event, so importing the document gives back every occurrence
+    pub fn export_range_ics(&self, start: NaiveDateTime, end: NaiveDateTime) -> String {
+        self.export_range_ics_where(start, end, |_| true)
+    }
+
+    /// export the events overlapping the window from start to end for which
+    /// `pred` returns true, in the same form as
+    /// [`EventCalendar::export_range_ics`]. `pred` is given the recurring
+    /// event itself, not each of its occurrences
+    pub fn export_range_ics_where<F: Fn(&Event) -> bool>(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        pred: F,
+    ) -> String {
+        ics_document(
+            self.iter()
+                .filter(|evt| pred(evt))
+                .flat_map(|evt| evt.occurrences_iter(start, end))
+                .map(|occ| match occ.master_id() {
+                    Some(master) => occ.to_ics_with_uid(occurrence_uid(master, occ.start()), None),
+                    None => occ.to_ics_vevent(),
+                }),
+        )
+    }
+
+    /// group events by tag in chronological order, an event appears under
+    /// each of its tags and events without tags are grouped under "untagged"
+    pub fn events_grouped_by_tag(&self) -> BTreeMap<String, Vec<&Event>> {
+        let mut groups: BTreeMap<String, Vec<&Event>> = BTreeMap::new();
+
+        for evt in &self.evts {
+            if evt.tags().is_empty() {
+                groups.entry("untagged".into()).or_default().push(evt);
+            }
+
+            for tag in evt.tags() {
+                groups.entry(tag.clone()).or_default().push(evt);
+            }
+        }
+
+        groups
+    }
+
+    /// find events whose name or tags contain `query`, ignoring case. Each
+    /// match is scored from 0 to 1 (exact name, then name prefix, then name
+    /// substring, then tags) and the best matches come first, ties in
+    /// chronological order
+    pub fn search(&self, query: &str) -> Vec<(&Event, f32)> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let score = |evt: &Event| -> f32 {
+            let name = evt.name().to_lowercase();
+            if name == query {
+                1.0
+            } else if name.starts_with(&query) {
+                0.75
+            } else if name.contains(&query) {
+                0.5
+            } else if evt.tags().iter().any(|t| t.to_lowercase() == query) {
+                0.4
+            } else if evt.tags().iter().any(|t| t.to_lowercase().contains(&query)) {
+                0.25
+            } else {
+                0.0
+            }
+        };
+
+        let mut hits: Vec<(&Event, f32)> = self
+            .iter()
+            .map(|evt| (evt, score(evt)))
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        // the sort is stable, so equal scores stay in chronological order
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+        hits
+    }
+
+    /// sum the hours spent on each tag during the ISO week (Monday to
+    /// Sunday) containing `week_start`, counting only the part of each event
+    /// inside the week. Events with several tags count toward each of them
+    /// and events without tags are counted under "untagged", as with
+    /// [`EventCalendar::events_grouped_by_tag`]
+    pub fn weekly_hours_by_tag(&self, week_start: NaiveDate) -> BTreeMap<String, f64> {
+        let monday =
+            week_start - Duration::days(week_start.weekday().num_days_from_monday().into());
+        let start = monday.and_time(day_start());
+        let end = add_saturating(start, Duration::weeks(1));
+        let mut hours: BTreeMap<String, f64> = BTreeMap::new();
+
+        for evt in self.overlapping(start, end) {
+            // open-ended events are cut off at the end of the week
+            let spent = evt.covered_until().min(end) - evt.start().max(start);
+            if spent <= Duration::zero() {
+                continue;
+            }
+            let spent = spent.num_seconds() as f64 / 3600.0;
+
+            if evt.tags().is_empty() {
+                *hours.entry("untagged".into()).or_default() += spent;
+            }
+            for tag in evt.tags() {
+                *hours.entry(tag.clone()).or_default() += spent;
+            }
+        }
+
+        hours
+    }
+
+    /// count events by the weekday of their start date, index 0 is Monday
+    pub fn events_per_weekday(&self) -> [usize; 7] {
+        let mut counts = [0; 7];
+
+        for evt in &self.evts {
+            counts[evt.start().weekday().num_days_from_monday() as usize] += 1;
+        }
+
+        counts
+    }
+
+    /// the events to save, see [`SavedCalendar`]
+    fn saved(&self) -> SavedCalendar<&Event> {
+        let events = self.iter().collect();
+        match self.archived.is_empty() {
+            true => SavedCalendar::Events(events),
+            false => SavedCalendar::WithArchive {
+                events,
+                archived: self.archived.values().collect(),
+            },
+        }
+    }
+
+    /// write the calendar to `w` as a JSON array of events in
+    /// chronological order. If events are archived the array is written as
+    /// `events` next to an `archived` array, both are read by
+    /// [`EventCalendar::load`]
+    pub fn save<W: std::io::Write>(&self, w: W) -> std::io::Result<()> {
+        serde_json::to_writer(w, &self.saved())?;
+        Ok(())
+    }
+
+    /// return the calendar as indented JSON that can be read with
+    /// [`EventCalendar::load`], in the form written by
+    /// [`EventCalendar::save`]. Events are in chronological order and keys
+    /// in field order, so the same calendar always gives the same output
+    pub fn to_pretty_json(&self) -> String {
+        serde_json::to_string_pretty(&self.saved()).unwrap()
+    }
+
+    /// return the earliest date on or after `from` without any events. None
+    /// if an open-ended event has started by then, since every later date
+    /// is busy
+    pub fn first_free_day(&self, from: NaiveDate) -> Option<NaiveDate> {
+        let mut candidate = from;
+
+        // events are sorted by start, so the candidate only ever moves forward
+        for evt in &self.evts {
+            if evt.start().date() > candidate {
+                break;
+            }
+
+            if evt.is_open_ended() {
+                return None;
+            }
+            if evt.last_date() >= candidate {
+                candidate = evt.last_date().succ_opt()?;
+            }
+        }
+
+        Some(candidate)
+    }
+
+    /// return the start of the first slot at or after `after` that is free
+    /// for `duration` and, if given, lies within `working_hours`. None if
+    /// the duration is not positive or does not fit in the working hours
+    pub fn next_free_slot(
+        &self,
+        duration: Duration,
+        after: NaiveDateTime,
+        working_hours: Option<WorkingHours>,
+    ) -> Option<NaiveDateTime> {
+        if duration <= Duration::zero() {
+            return None;
+        }
+        if let Some(hours) = working_hours {
+            if hours.end - hours.start < duration {
+                return None;
+            }
+        }
+
+        let mut candidate = after;
+        loop {
+            if let Some(hours) = working_hours {
+                candidate = hours.next_open(candidate)?;
+                let end = candidate.checked_add_signed(duration)?;
+                if end > candidate.date().and_time(hours.end) {
+                    candidate = candidate.date().succ_opt()?.and_time(hours.start);
+                }
+            }
+            let end = candidate.checked_add_signed(duration)?;
+
+            // the candidate only ever moves forward past the busy events
+            let busy_until = self
+                .evts
+                .iter()
+                .take_while(|evt| evt.start() < end)
+                .filter(|evt| evt.covered_until() > candidate)
+                .map(|evt| evt.covered_until())
+                .max();
+
+            match busy_until {
+                Some(until) => candidate = until,
+                None => return Some(candidate),
+            }
+        }
+    }
+
+    /// return every free interval between start and end that is at least
+    /// `min` long, in chronological order. All day events are busy up to
+    /// midnight, see [`Event::overlaps`]
+    pub fn find_gaps(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        min: Duration,
+    ) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let mut gaps = Vec::new();
+        let mut cursor = start;
+
+        // events are sorted by start, so everything before cursor is busy
+        for evt in self.evts.iter().take_while(|evt| evt.start() < end) {
+            if evt.start() > cursor {
+                gaps.push((cursor, evt.start()));
+            }
+            cursor = cursor.max(evt.covered_until());
+        }
+        if cursor < end {
+            gaps.push((cursor, end));
+        }
+
+        gaps.retain(|(from, to)| *to - *from >= min);
+        gaps
+    }
+
+    /// create an event named `name` in the next free slot found by
+    /// [`EventCalendar::next_free_slot`], returning its id
+    pub fn schedule(
+        &mut self,
+        name: String,
+        duration: Duration,
+        after: NaiveDateTime,
+        working_hours: Option<WorkingHours>,
+    ) -> Option<Uuid> {
+        let start = self.next_free_slot(duration, after, working_hours)?;
+        let evt = Event::new(name, &start.date())
+            .set_end(start + duration)
+            .and_then(|evt| evt.set_start(start))
+            .ok()?;
+
+        let id = *evt.id();
+        self.add_event(evt);
+        Some(id)
+    }
+
+    /// push overlapping events forward so that no two events overlap,
+    /// leaving at least `gap` between an event and the one it was moved
+    /// behind. Events keep their duration and chronological order, and the
+    /// ids and new start times of the moved events are returned. Events
+    /// starting after an open-ended event cannot be moved past it and are
+    /// left where they are
+    pub fn reschedule_conflicts(&mut self, gap: Duration) -> Vec<(Uuid, NaiveDateTime)> {
+        let gap = gap.max(Duration::zero());
+        let mut moves = Vec::new();
+        let mut busy_until: Option<NaiveDateTime> = None;
+
+        for evt in &self.evts {
+            let new_start = match busy_until {
+                // nothing ends after an open-ended event to move behind
+                Some(OPEN_END) => evt.start(),
+                Some(until) if evt.start() < until => add_saturating(until, gap),
+                _ => evt.start(),
+            };
+
+            let new_end = add_saturating(new_start, evt.end() - evt.start());
+            busy_until = Some(busy_until.map_or(new_end, |until| until.max(new_end)));
+
+            if new_start != evt.start() {
+                moves.push((*evt.id(), new_start - evt.start()));
+            }
+        }
+
+        self.shift_events(moves)
+    }
+
+    /// pack the timed events starting on `date` back to back from
+    /// `start_at`, leaving `gap` between each one. Events keep their
+    /// duration and chronological order, all day and open-ended events are
+    /// left where they are, and the ids and new start times of the moved
+    /// events are returned
+    pub fn defragment_day(
+        &mut self,
+        date: NaiveDate,
+        start_at: NaiveTime,
+        gap: Duration,
+    ) -> Vec<(Uuid, NaiveDateTime)> {
+        let gap = gap.max(Duration::zero());
+        let mut moves = Vec::new();
+        let mut next_start = date.and_time(start_at);
+
+        for evt in self.events_on_day(date) {
+            if evt.start().date() != date || evt.is_all_day() || evt.is_open_ended() {
+                continue;
+            }
+
+            if next_start != evt.start() {
+                moves.push((*evt.id(), next_start - evt.start()));
+            }
+            next_start = add_saturating(add_saturating(next_start, evt.end() - evt.start()), gap);
+        }
+
+        self.shift_events(moves)
+    }
+
+    /// return every pair of overlapping events, each pair is ordered
+    /// chronologically and pairs are sorted by the start of their later event.
+    /// Uses a sweep over the sorted events so only events that are still
+    /// running are compared against each other
+    pub fn overlapping_pairs(&self) -> Vec<(&Event, &Event)> {
+        let mut pairs = Vec::new();
+        let mut active: Vec<&Event> = Vec::new();
+
+        for evt in self.iter() {
+            active.retain(|running| running.covered_until() > evt.start());
+            pairs.extend(active.iter().map(|running| (*running, evt)));
+            active.push(evt);
+        }
+
+        pairs
+    }
+
+    /// encode the calendar in a compact binary format, much smaller and
+    /// faster to read than [`EventCalendar::save`]. Archived events are
+    /// encoded after the active ones
+    #[cfg(feature = "binary")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        use crate::event::EventRecord;
+
+        let records: Vec<EventRecord> = self.iter().map(EventRecord::from).collect();
+        let archived: Vec<EventRecord> = self.archived.values().map(EventRecord::from).collect();
+        // serializing into a Vec cannot fail
+        let mut bytes = bincode::serialize(&records).unwrap();
+        if !archived.is_empty() {
+            bytes.extend(bincode::serialize(&archived).unwrap());
+        }
+        bytes
+    }
+
+    /// return all events overlapping `candidate` in chronological order,
+    /// the candidate itself is never reported
+    pub fn conflicts(&self, candidate: &Event) -> Vec<&Event> {
+        self.conflicts_with_buffer(candidate, Duration::zero())
+    }
+
+    /// return all events overlapping `candidate` or less than `buffer` away
+    /// from it, see [`Event::overlaps_with_buffer`]
+    pub fn conflicts_with_buffer(&self, candidate: &Event, buffer: Duration) -> Vec<&Event> {
+        let (start, end) = conflict_window(candidate, buffer);
+        let mut evts: Vec<&Rc<Event>> = self
+            .overlapping(start, end)
+            .filter(|evt| evt.id() != candidate.id())
+            .filter(|evt| evt.overlaps_with_buffer(candidate, buffer))
+            .collect();
+        evts.sort();
+        evts.into_iter().map(Rc::as_ref).collect()
+    }
+
+    /// returns true if any other event overlaps `candidate`, stopping at the
+    /// first one found. Cheaper than checking [`EventCalendar::conflicts`]
+    /// for emptiness when the conflicting events themselves are not needed
+    pub fn has_conflict(&self, candidate: &Event) -> bool {
+        let (start, end) = conflict_window(candidate, Duration::zero());
+        let mut found = false;
+
+        self.intervals.for_each_overlapping(start, end, |id| {
+            let evt = self.indexed(&id);
+            found = *evt.id() != *candidate.id() && evt.overlaps(candidate);
+            !found
+        });
+
+        found
+    }
+
+    /// return the id and start time of every event occurrence within
+    /// `window` that overlaps `candidate`, expanding recurring events into
+    /// their individual occurrences. Occurrences are reported with the id of
+    /// their recurring event and the candidate itself is never reported
+    pub fn conflicts_expanded(
+        &self,
+        candidate: &Event,
+        window: (NaiveDateTime, NaiveDateTime),
+    ) -> Vec<(Uuid, NaiveDateTime)> {
+        let (start, end) = window;
+
+        self.iter()
+            .filter(|evt| evt.id() != candidate.id())
+            .flat_map(|evt| evt.occurrences_iter(start, end))
+            .filter(|occ| occ.overlaps(candidate))
+            .map(|occ| (*occ.master_id().unwrap_or(occ.id()), occ.start()))
+            .collect()
+    }
+
+    /// return all events touching `date` in chronological order, events that
+    /// end exactly at midnight do not touch the day they end on
+    pub fn events_on_day(&self, date: NaiveDate) -> Vec<&Event> {
+        self.iter()
+            .take_while(|evt| evt.start().date() <= date)
+            .filter(|evt| evt.last_date() >= date)
+            .collect()
+    }
+
+    /// return the concrete events taking place on `date` in chronological
+    /// order, with recurring events expanded into their occurrences as by
+    /// [`Event::occurrences_iter`]
+    pub fn occurrences_on(&self, date: NaiveDate) -> Vec<Event> {
+        let start = date.and_time(day_start());
+        let end = date
+            .succ_opt()
+            .map_or(NaiveDateTime::MAX, |next| next.and_time(day_start()));
+
+        let mut occs: Vec<Event> = self
+            .iter()
+            .take_while(|evt| evt.start() < end)
+            .flat_map(|evt| evt.occurrences_iter(start, end))
+            .collect();
+        occs.sort_by(Event::cmp_chronological);
+        occs
+    }
+
+    /// return a plain text agenda for `date` with one line per event, the
+    /// part of the day each event covers followed by its name. Events
+    /// covering the whole day are listed first
+    pub fn day_agenda(&self, date: NaiveDate) -> String {
+        let mut all_day = Vec::new();
+        let mut timed = Vec::new();
+
+        for evt in self.events_on_day(date) {
+            let start = evt.start().max(date.and_time(day_start()));
+            let end = evt.end().min(date.and_time(day_end()));
+            if start >= end {
+                continue;
+            }
+
+            if start.time() == day_start() && end.time() == day_end() {
+                all_day.push(format!("  {:<11}  {}", "all day", evt.name()));
+            } else {
+                let times = format!("{}-{}", start.format("%H:%M"), end.format("%H:%M"));
+                timed.push(format!("  {:<11}  {}", times, evt.name()));
+            }
+        }
+
+        let mut agenda = format!("{}\n", date.format("%A %Y-%m-%d"));
+        if all_day.is_empty() && timed.is_empty() {
+            agenda.push_str("  no events\n");
+        }
+        for line in all_day.into_iter().chain(timed) {
+            agenda.push_str(&line);
+            agenda.push('\n');
+        }
+        agenda
+    }
+
+    /// returns true if any event touches `date`, like
+    /// [`EventCalendar::events_on_day`] but stopping at the first one. An
+    /// open-ended event makes every date from its start busy
+    pub fn busy_on(&self, date: NaiveDate) -> bool {
+        self.iter()
+            .take_while(|evt| evt.start().date() <= date)
+            .any(|evt| evt.last_date() >= date)
+    }
+
+    /// return all events taking place on the current day according to `clock`
+    pub fn events_today(&self, clock: &impl Clock) -> Vec<&Event> {
+        self.events_on_day(clock.now().date())
+    }
+
+    /// iterate over every date from start to end (inclusive), yielding the
+    /// events that touch each date in chronological order. Dates without any
+    /// events are yielded with an empty Vec
+    pub fn iter_days(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> impl Iterator<Item = (NaiveDate, Vec<&Event>)> {
+        start
+            .iter_days()
+            .take_while(move |date| *date <= end)
+            .map(move |date| (date, self.events_on_day(date)))
+    }
+
+    /// returns true if the id and chronological indexes hold exactly the
+    /// same events and every event starts before it ends
+    pub fn validate(&self) -> bool {
+        self.ids.len() == self.evts.len()
+            && self.ids.len() == self.by_start.len()
+            && self.ids.len() == self.intervals.len()
+            && self.evts.iter().all(|evt| {
+                self.ids
+                    .get(evt.id())
+                    .is_some_and(|by_id| Rc::ptr_eq(by_id, evt))
+                    && self.by_start.contains(&(evt.start(), *evt.id()))
+                    && evt.is_valid()
+            })
+    }
+
+    /// rebuild all indexes into fresh containers, releasing memory left
+    /// behind by bulk removals, see [`EventCalendar::reindex`]
+    pub fn compact(&mut self) {
+        self.ids.shrink();
+        self.reindex();
+        self.archived = std::mem::take(&mut self.archived).into_iter().collect();
+    }
+
+    /// rebuild the chronological, start time and interval indexes from the
+    /// id index, bringing them back in sync with it. Events in the store are
+    /// indexed as they are, so a store holding an invalid event still fails
+    /// [`EventCalendar::validate`] afterwards
+    pub fn reindex(&mut self) {
+        self.evts = self.ids.events().cloned().collect();
+        self.by_start = self
+            .ids
+            .events()
+            .map(|evt| (evt.start(), *evt.id()))
+            .collect();
+        self.intervals.clear();
+        for evt in self.ids.events() {
+            self.intervals.insert(evt.start(), evt.end(), *evt.id());
+        }
+    }
+
+    /// look up an event found in one of the other indexes, which only hold
+    /// ids that are in the store
+    fn indexed(&self, id: &Uuid) -> &Rc<Event> {
+        self.ids.get(id).expect("calendar indexes out of sync")
+    }
 }
@@ -0,0 +1,80 @@
+use chrono::NaiveDateTime;
+use std::cell::RefCell;
+
+/// Source of the current time for methods that depend on "now",
+/// allowing a fixed time to be used in place of the system clock
+pub trait Clock {
+    /// returns the current date and time
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// Clock that reads the local time of the system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        chrono::Local::now().naive_local()
+    }
+}
+
+/// Clock that always returns the same time, useful for tests
+///
+/// # Examples
+/// ```
+/// use calib::{Clock, FixedClock};
+/// use chrono::NaiveDate;
+///
+/// let time = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+/// let clock = FixedClock(time);
+/// assert_eq!(clock.now(), time)
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub NaiveDateTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+}
+
+thread_local! {
+    /// clock set with [`with_clock`] on the current thread
+    static CLOCK: RefCell<Option<Box<dyn Clock>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `clock` stamping the last modified time of events changed
+/// on the current thread, in place of the system time in UTC
+///
+/// # Examples
+/// ```
+/// use calib::{with_clock, Event, FixedClock};
+/// use chrono::NaiveDate;
+///
+/// let time = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+/// let mut e = Event::new("A".into(), &time.date());
+/// with_clock(FixedClock(time), || e.set_name("B".into()));
+/// assert_eq!(e.modified(), time)
+/// ```
+pub fn with_clock<C: Clock + 'static, R>(clock: C, f: impl FnOnce() -> R) -> R {
+    /// puts back the previous clock when dropped, even if `f` panics
+    struct Restore(Option<Box<dyn Clock>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let prev = self.0.take();
+            CLOCK.with(|clock| *clock.borrow_mut() = prev);
+        }
+    }
+
+    let _restore = Restore(CLOCK.with(|prev| prev.replace(Some(Box::new(clock)))));
+    f()
+}
+
+/// the time to stamp a change with, read from the clock set with
+/// [`with_clock`] or else the system time in UTC
+pub(crate) fn modified_now() -> NaiveDateTime {
+    CLOCK
+        .with(|clock| clock.borrow().as_ref().map(|clock| clock.now()))
+        .unwrap_or_else(|| chrono::Utc::now().naive_utc())
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+/// the basic CSS color keywords and their values
+const NAMED_COLORS: [(&str, Color); 17] = [
+    ("aqua", Color::rgb(0x00, 0xff, 0xff)),
+    ("black", Color::rgb(0x00, 0x00, 0x00)),
+    ("blue", Color::rgb(0x00, 0x00, 0xff)),
+    ("fuchsia", Color::rgb(0xff, 0x00, 0xff)),
+    ("gray", Color::rgb(0x80, 0x80, 0x80)),
+    ("green", Color::rgb(0x00, 0x80, 0x00)),
+    ("lime", Color::rgb(0x00, 0xff, 0x00)),
+    ("maroon", Color::rgb(0x80, 0x00, 0x00)),
+    ("navy", Color::rgb(0x00, 0x00, 0x80)),
+    ("olive", Color::rgb(0x80, 0x80, 0x00)),
+    ("orange", Color::rgb(0xff, 0xa5, 0x00)),
+    ("purple", Color::rgb(0x80, 0x00, 0x80)),
+    ("red", Color::rgb(0xff, 0x00, 0x00)),
+    ("silver", Color::rgb(0xc0, 0xc0, 0xc0)),
+    ("teal", Color::rgb(0x00, 0x80, 0x80)),
+    ("white", Color::rgb(0xff, 0xff, 0xff)),
+    ("yellow", Color::rgb(0xff, 0xff, 0x00)),
+];
+
+/// An RGB color for displaying an event, stored and serialized as
+/// `#rrggbb`
+///
+/// # Examples
+/// ```
+/// use calib::{parse_color, Color};
+///
+/// assert_eq!(parse_color("#F80"), Some(Color::rgb(0xff, 0x88, 0x00)));
+/// assert_eq!(parse_color("navy").unwrap().to_hex(), "#000080");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// returns the color as lowercase `#rrggbb`
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_hex()
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_color(&value).ok_or_else(|| format!("invalid color: {value}"))
+    }
+}
+
+/// parse a color written as `#rgb`, `#rrggbb` or one of the basic CSS
+/// color names (e.g. `teal`), ignoring case and surrounding whitespace
+pub fn parse_color(s: &str) -> Option<Color> {
+    let s = s.trim();
+
+    let Some(hex) = s.strip_prefix('#') else {
+        return NAMED_COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, color)| *color);
+    };
+
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i * len..(i + 1) * len], 16);
+
+    match hex.len() {
+        // each digit is doubled, #f80 is #ff8800
+        3 => Some(Color::rgb(
+            channel(0, 1).ok()? * 0x11,
+            channel(1, 1).ok()? * 0x11,
+            channel(2, 1).ok()? * 0x11,
+        )),
+        6 => Some(Color::rgb(
+            channel(0, 2).ok()?,
+            channel(1, 2).ok()?,
+            channel(2, 2).ok()?,
+        )),
+        _ => None,
+    }
+}
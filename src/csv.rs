@@ -0,0 +1,107 @@
+use chrono::NaiveDateTime;
+
+use super::{Event, ImportError};
+
+/// formats accepted for the start and end columns, e.g. 2023-01-01T09:00
+const DATE_TIME_FMTS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// split a CSV record into its fields. Fields may be wrapped in double
+/// quotes to hold commas, with `""` standing for a quote inside them
+pub(crate) fn split_record(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+
+    // an unterminated quote
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+/// returns true if `header` names at least the name, start and end columns
+pub(crate) fn is_header(header: &str) -> bool {
+    let columns = split_record(header).unwrap_or_default();
+    ["name", "start", "end"]
+        .iter()
+        .all(|col| columns.iter().any(|c| c.trim().eq_ignore_ascii_case(col)))
+}
+
+/// read events from CSV text whose first row names the columns. The name,
+/// start and end columns are required and an optional tags column holds
+/// tags separated by `;`, other columns are ignored. Times are written as
+/// `2023-01-01T09:00[:00]`, with a space allowed in place of the `T`
+pub(crate) fn parse_events(text: &str) -> Result<Vec<Event>, ImportError> {
+    let mut lines = text
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let err = |line: usize, reason: &str| ImportError::Csv {
+        line: line + 1,
+        reason: reason.to_string(),
+    };
+
+    let (_, header) = lines.next().ok_or_else(|| err(0, "missing header row"))?;
+    let header = split_record(header).ok_or_else(|| err(0, "unterminated quote"))?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|c| c.trim().eq_ignore_ascii_case(name))
+    };
+    let missing = |name| err(0, &format!("missing {name} column"));
+    let name_col = column("name").ok_or_else(|| missing("name"))?;
+    let start_col = column("start").ok_or_else(|| missing("start"))?;
+    let end_col = column("end").ok_or_else(|| missing("end"))?;
+    let tags_col = column("tags");
+
+    let mut evts = Vec::new();
+    for (i, line) in lines {
+        let fields = split_record(line).ok_or_else(|| err(i, "unterminated quote"))?;
+        let field = |col: usize| fields.get(col).map(|f| f.trim()).unwrap_or_default();
+        let time = |col: usize| {
+            DATE_TIME_FMTS
+                .iter()
+                .find_map(|fmt| NaiveDateTime::parse_from_str(field(col), fmt).ok())
+                .ok_or_else(|| err(i, &format!("invalid date/time value: {}", field(col))))
+        };
+
+        let (start, end) = (time(start_col)?, time(end_col)?);
+        // setting the end first keeps the default all day end out of the way
+        let evt = Event::new(field(name_col).to_string(), &start.date())
+            .set_end(end)
+            .and_then(|evt| evt.set_start(start))
+            .map_err(|e| err(i, &e.to_string()))?;
+        let tags = tags_col
+            .map(|col| field(col).split(';'))
+            .into_iter()
+            .flatten()
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(String::from);
+
+        evts.push(evt.with_tags(tags));
+    }
+
+    Ok(evts)
+}
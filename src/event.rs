@@ -1,35 +1,320 @@
 use super::*;
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
-use serde::Serialize;
+use crate::ics;
+use crate::recur::{self, Recurrence, MAX_OCCURRENCES};
+use chrono::{
+    DateTime, DurationRound, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use uuid::Uuid;
 
+/// the current time used to stamp the `modified` field, see [`with_clock`]
+fn now() -> NaiveDateTime {
+    crate::clock::modified_now()
+}
+
+/// convert a local time in `tz` to UTC, times repeated when clocks go back
+/// resolve to the earlier instant and times skipped when clocks go forward
+/// use the offset in effect before the change
+fn local_to_utc<Z: TimeZone>(tz: &Z, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => dt.with_timezone(&Utc),
+        LocalResult::None => {
+            // a day earlier is safely before the transition for any offset
+            let day_before = local - chrono::Duration::days(1);
+            let offset = tz.offset_from_utc_datetime(&day_before).fix();
+            let utc = local - chrono::Duration::seconds(offset.local_minus_utc().into());
+            Utc.from_utc_datetime(&utc)
+        }
+    }
+}
+
+/// end time of an open-ended event, so that it sorts after fixed events
+/// with the same start and overlaps everything after its start
+pub(crate) const OPEN_END: NaiveDateTime = NaiveDateTime::MAX;
+
+/// add `by` to `dt`, saturating at the earliest and latest representable
+/// times so open-ended events never overflow
+pub(crate) fn add_saturating(dt: NaiveDateTime, by: chrono::Duration) -> NaiveDateTime {
+    dt.checked_add_signed(by)
+        .unwrap_or(if by < chrono::Duration::zero() {
+            NaiveDateTime::MIN
+        } else {
+            NaiveDateTime::MAX
+        })
+}
+
+/// (de)serializes the end of an open-ended event as null
+mod open_end {
+    use super::OPEN_END;
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(end: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+        (*end != OPEN_END).then_some(end).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        Ok(Option::deserialize(deserializer)?.unwrap_or(OPEN_END))
+    }
+}
+
+/// deserializes custom properties, rejecting keys that
+/// [`Event::set_property`] would not accept
+fn property_keys<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    let properties = BTreeMap::<String, String>::deserialize(deserializer)?;
+    match properties.keys().find(|key| !ics::is_property_key(key)) {
+        Some(key) => Err(serde::de::Error::custom(EventError::InvalidPropertyKey(
+            key.clone(),
+        ))),
+        None => Ok(properties),
+    }
+}
+
+/// how far before the end of the day an event may end and still be
+/// treated as all day by [`Event::normalize_all_day`]
+const ALL_DAY_TOLERANCE_MINUTES: i64 = 5;
+
+/// shortest duration an event can have, used when clamping times
+const MIN_DURATION_SECONDS: i64 = 1;
+
+/// (de)serializes an event id as a lowercase hyphenated string regardless of
+/// uuid's defaults, so saved files stay stable. Any form accepted by
+/// [`Uuid::parse_str`] (simple, braced or urn) is read back
+mod hyphenated_uuid {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use uuid::Uuid;
+
+    pub fn serialize<S: Serializer>(id: &Uuid, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&id.as_hyphenated().to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Uuid::parse_str(&s).map_err(de::Error::custom)
+    }
+}
+
+/// A partial update for an [`Event`], only the fields that are set are
+/// changed when applied with [`Event::apply_patch`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EventPatch {
+    pub name: Option<String>,
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+}
+
 // NOTE: Keep fields in order based on how comparisons should go,
-// see Ord/PartialOrd Trait derive documentation
+// see Ord/PartialOrd Trait derive documentation. serde also writes fields
+// in this order, so new fields go at the end to keep saved JSON stable
 /// Struct to represent a given event on the calendar
-#[derive(PartialOrd, Ord, PartialEq, Eq, Debug, Serialize, Clone)]
+#[derive(PartialOrd, Ord, PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct Event {
+    start: NaiveDateTime,
+    #[serde(with = "open_end")]
+    end: NaiveDateTime,
+    name: String,
+    #[serde(with = "hyphenated_uuid")]
+    id: Uuid,
+    // events saved before modified was tracked default to the unix epoch
+    #[serde(default)]
+    modified: NaiveDateTime,
+    // stored as seconds east of UTC since FixedOffset does not implement Ord
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    offset: Option<i32>,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "property_keys"
+    )]
+    properties: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recurrence: Option<Recurrence>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    master_id: Option<Uuid>,
+    // IANA name of the time zone the start and end times are in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zone: Option<String>,
+    // replacements for single occurrences, keyed by their original date
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    overrides: BTreeMap<NaiveDate, Event>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<Color>,
+}
+
+/// Borrowed view of an [`Event`] written by [`Event::serialize_compact`],
+/// in the same field order as the event but without `modified`
+#[derive(Serialize)]
+struct CompactEvent<'a> {
+    start: NaiveDateTime,
+    #[serde(serialize_with = "open_end::serialize")]
+    end: NaiveDateTime,
+    name: &'a str,
+    #[serde(serialize_with = "hyphenated_uuid::serialize")]
+    id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<i32>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    properties: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    tags: &'a BTreeSet<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recurrence: Option<&'a Recurrence>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    master_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zone: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    overrides: &'a BTreeMap<NaiveDate, Event>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<Color>,
+}
+
+/// The id, name and times of an [`Event`], for listings that do not need
+/// the tags, properties and other details
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventSummary {
+    #[serde(with = "hyphenated_uuid")]
+    pub id: Uuid,
+    pub name: String,
+    pub start: NaiveDateTime,
+    #[serde(with = "open_end")]
+    pub end: NaiveDateTime,
+}
+
+/// Plain copy of an [`Event`]'s fields without the serde attributes used for
+/// JSON, as non self-describing formats like bincode cannot skip fields
+#[cfg(feature = "binary")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EventRecord {
     start: NaiveDateTime,
     end: NaiveDateTime,
     name: String,
     id: Uuid,
+    modified: NaiveDateTime,
+    offset: Option<i32>,
+    properties: BTreeMap<String, String>,
+    tags: BTreeSet<String>,
+    recurrence: Option<Recurrence>,
+    master_id: Option<Uuid>,
+    zone: Option<String>,
+    overrides: BTreeMap<NaiveDate, EventRecord>,
+    color: Option<Color>,
+}
+
+#[cfg(feature = "binary")]
+impl From<&Event> for EventRecord {
+    fn from(evt: &Event) -> Self {
+        let evt = evt.clone();
+        Self {
+            start: evt.start,
+            end: evt.end,
+            name: evt.name,
+            id: evt.id,
+            modified: evt.modified,
+            offset: evt.offset,
+            properties: evt.properties,
+            tags: evt.tags,
+            recurrence: evt.recurrence,
+            master_id: evt.master_id,
+            zone: evt.zone,
+            overrides: evt
+                .overrides
+                .iter()
+                .map(|(date, occ)| (*date, occ.into()))
+                .collect(),
+            color: evt.color,
+        }
+    }
+}
+
+#[cfg(feature = "binary")]
+impl From<EventRecord> for Event {
+    fn from(rec: EventRecord) -> Self {
+        Self {
+            start: rec.start,
+            end: rec.end,
+            name: rec.name,
+            id: rec.id,
+            modified: rec.modified,
+            offset: rec.offset,
+            properties: rec.properties,
+            tags: rec.tags,
+            recurrence: rec.recurrence,
+            master_id: rec.master_id,
+            zone: rec.zone,
+            overrides: rec
+                .overrides
+                .into_iter()
+                .map(|(date, occ)| (date, occ.into()))
+                .collect(),
+            color: rec.color,
+        }
+    }
+}
+
+/// Events hash by id only. This agrees with the field based `Eq`, since
+/// equal events always share an id, but two versions of the same event
+/// (e.g. before and after an edit) hash alike while comparing unequal
+impl std::hash::Hash for Event {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
 impl Event {
     /// given a start and end time determine whether they would be valid
-    fn start_end_times_valid(st: &NaiveDateTime, end: &NaiveDateTime) -> bool {
+    pub(crate) fn start_end_times_valid(st: &NaiveDateTime, end: &NaiveDateTime) -> bool {
         end.signed_duration_since(*st).num_seconds().is_positive()
     }
 
+    /// returns true if the event starts before it ends, which holds for any
+    /// event built through the setters but not necessarily for deserialized
+    /// ones
+    pub fn is_valid(&self) -> bool {
+        Event::start_end_times_valid(&self.start, &self.end)
+    }
+
+    /// build an event without checking that start is before end
+    #[cfg(test)]
+    pub(crate) fn new_unchecked(name: String, start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        Self {
+            start,
+            end,
+            ..Event::new(name, &start.date())
+        }
+    }
+
     /// return the NaiveDate component of the start field
     pub fn start(&self) -> NaiveDateTime {
         self.start
     }
 
-    /// return the NaiveDate component of the end field
+    /// return the NaiveDate component of the end field, open-ended events
+    /// end at `NaiveDateTime::MAX`
     pub fn end(&self) -> NaiveDateTime {
         self.end
     }
 
+    /// returns the end time, or None if the event is open-ended
+    pub fn fixed_end(&self) -> Option<NaiveDateTime> {
+        (!self.is_open_ended()).then_some(self.end)
+    }
+
+    /// returns true if the event has no known end, see
+    /// [`Event::new_open_ended`]
+    pub fn is_open_ended(&self) -> bool {
+        self.end == OPEN_END
+    }
+
     /// returns the name of the event
     pub fn name(&self) -> &str {
         &self.name
@@ -40,6 +325,200 @@ impl Event {
         &self.id
     }
 
+    /// returns the UTC offset the start and end times are in, if any.
+    /// Events without an offset use floating (local) times
+    pub fn offset(&self) -> Option<FixedOffset> {
+        self.offset.and_then(FixedOffset::east_opt)
+    }
+
+    /// returns the time zone the start and end times are in, if any
+    pub fn zone(&self) -> Option<Tz> {
+        self.zone.as_deref().and_then(|name| name.parse().ok())
+    }
+
+    /// convert the start and end times to UTC using the event's time zone,
+    /// or its UTC offset if it has no zone. None for floating times.
+    ///
+    /// Local times that occur twice when clocks go back resolve to the
+    /// earlier instant, and times skipped when clocks go forward are read
+    /// with the offset in effect before the change
+    pub fn to_utc(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let convert = |local: NaiveDateTime| -> Option<DateTime<Utc>> {
+            if local == OPEN_END {
+                return Some(DateTime::<Utc>::MAX_UTC);
+            }
+            match self.zone() {
+                Some(tz) => Some(local_to_utc(&tz, local)),
+                None => Some(local_to_utc(&self.offset()?, local)),
+            }
+        };
+        Some((convert(self.start)?, convert(self.end)?))
+    }
+
+    /// like [`Event::to_utc`], but floating times are read as local times
+    /// in `tz` instead of giving None
+    pub(crate) fn to_utc_or(&self, tz: &Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+        self.to_utc().unwrap_or_else(|| {
+            let convert = |local: NaiveDateTime| match local {
+                OPEN_END => DateTime::<Utc>::MAX_UTC,
+                _ => local_to_utc(tz, local),
+            };
+            (convert(self.start), convert(self.end))
+        })
+    }
+
+    /// returns when the event was last changed, in UTC unless a clock was
+    /// set with [`with_clock`](crate::with_clock)
+    pub fn modified(&self) -> NaiveDateTime {
+        self.modified
+    }
+
+    /// returns the value of a custom property
+    pub fn get_property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    /// returns all custom properties ordered by key
+    pub fn properties(&self) -> &BTreeMap<String, String> {
+        &self.properties
+    }
+
+    /// returns the last date the event occupies, an event ending exactly
+    /// at midnight does not occupy the day it ends on
+    pub(crate) fn last_date(&self) -> NaiveDate {
+        if self.end.time() == day_start() && self.end.date() > self.start.date() {
+            self.end.date().pred_opt().unwrap_or(self.end.date())
+        } else {
+            self.end.date()
+        }
+    }
+
+    /// returns how many dates the event touches, see [`Event::last_date`]
+    pub fn days_spanned(&self) -> i64 {
+        (self.last_date() - self.start.date()).num_days() + 1
+    }
+
+    /// returns the id, name and times of the event
+    pub fn summary(&self) -> EventSummary {
+        EventSummary {
+            id: self.id,
+            name: self.name.clone(),
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    /// the instant the event stops taking up time, all day events cover
+    /// their last date up to midnight rather than stopping at [`day_end`]
+    pub(crate) fn covered_until(&self) -> NaiveDateTime {
+        if !self.is_all_day() {
+            return self.end;
+        }
+
+        self.end
+            .date()
+            .succ_opt()
+            .map_or(self.end, |next| next.and_time(day_start()))
+    }
+
+    /// returns true if the two events share any time, events where one
+    /// ends exactly when the other starts do not overlap. All day events
+    /// cover their dates entirely, including the last second before midnight
+    pub fn overlaps(&self, other: &Event) -> bool {
+        self.start < other.covered_until() && other.start < self.covered_until()
+    }
+
+    /// returns the whole minutes the two events share, rounded down, 0 if
+    /// they do not overlap. Like [`Event::overlaps`] all day events cover
+    /// their dates up to midnight
+    pub fn minutes_overlap(&self, other: &Event) -> i64 {
+        let start = self.start.max(other.start);
+        let end = self.covered_until().min(other.covered_until());
+        match end > start {
+            true => (end - start).num_minutes(),
+            false => 0,
+        }
+    }
+
+    /// returns true if the two events overlap or are less than `buffer`
+    /// apart, e.g. to leave travel time between meetings
+    pub fn overlaps_with_buffer(&self, other: &Event, buffer: chrono::Duration) -> bool {
+        self.start < add_saturating(other.covered_until(), buffer)
+            && other.start < add_saturating(self.covered_until(), buffer)
+    }
+
+    /// compare two events strictly by start and then end time, unlike the
+    /// derived `Ord` this does not break ties by name or id
+    pub fn cmp_chronological(a: &Event, b: &Event) -> std::cmp::Ordering {
+        (a.start, a.end).cmp(&(b.start, b.end))
+    }
+
+    /// trim the event to the working hours of its start date, returning
+    /// None if it falls entirely outside them. An event running past the
+    /// end of the day, including an open-ended one, is cut off at the end
+    /// of that day's working hours
+    pub fn clamp_to_working_hours(self, wh: &WorkingHours) -> Option<Event> {
+        let date = self.start.date();
+        let start = self.start.max(date.and_time(wh.start));
+        let end = self.end.min(date.and_time(wh.end));
+
+        if !Event::start_end_times_valid(&start, &end) {
+            return None;
+        }
+        Some(Event {
+            start,
+            end,
+            modified: now(),
+            ..self
+        })
+    }
+
+    /// returns whichever of the two events starts first, ties are broken
+    /// by end time and then by id so the result never depends on the order
+    /// of the arguments
+    pub fn earlier<'a>(&'a self, other: &'a Event) -> &'a Event {
+        match (self.start, self.end, self.id) <= (other.start, other.end, other.id) {
+            true => self,
+            false => other,
+        }
+    }
+
+    /// returns whichever of the two events starts last, the opposite of
+    /// [`Event::earlier`]
+    pub fn later<'a>(&'a self, other: &'a Event) -> &'a Event {
+        match std::ptr::eq(self.earlier(other), self) {
+            true => other,
+            false => self,
+        }
+    }
+
+    /// returns true if the event covers whole days, starting at
+    /// [`day_start`] and ending at [`day_end`]
+    pub fn is_all_day(&self) -> bool {
+        self.start.time() == day_start() && self.end.time() == day_end()
+    }
+
+    /// returns the tags of the event in sorted order
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    /// returns the id of the recurring event this event is an occurrence of,
+    /// None for standalone events
+    pub fn master_id(&self) -> Option<&Uuid> {
+        self.master_id.as_ref()
+    }
+
+    /// returns how the event repeats, if it does
+    pub fn recurrence(&self) -> Option<&Recurrence> {
+        self.recurrence.as_ref()
+    }
+
+    /// returns true if the event has the given tag
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
     /// Create an Event with a name and date, defaults to an
     /// all day event starting at 00:00:00 and ending at 23:59:59
     pub fn new(name: String, date: &NaiveDate) -> Self {
@@ -48,6 +527,26 @@ impl Event {
             start: NaiveDateTime::new(*date, day_start()),
             end: NaiveDateTime::new(*date, day_end()),
             id: Uuid::new_v4(),
+            modified: now(),
+            offset: None,
+            properties: BTreeMap::new(),
+            tags: BTreeSet::new(),
+            recurrence: None,
+            master_id: None,
+            zone: None,
+            overrides: BTreeMap::new(),
+            color: None,
+        }
+    }
+
+    /// Create an event starting at `start` without a known end (e.g. a trip
+    /// still being planned). It is treated as lasting forever by overlap
+    /// and range queries until an end is set with [`Event::set_end`]
+    pub fn new_open_ended(name: String, start: NaiveDateTime) -> Self {
+        Self {
+            start,
+            end: OPEN_END,
+            ..Event::new(name, &start.date())
         }
     }
 
@@ -58,7 +557,11 @@ impl Event {
         // results in an InvalidStartTime error, on success returns the new start time
         if Event::start_end_times_valid(&start, &self.end) {
             // lol literally the first time ive used this syntax
-            Ok(Event { start, ..self })
+            Ok(Event {
+                start,
+                modified: now(),
+                ..self
+            })
         } else {
             // if the new start time is invalid then return an error
             Err(EventError::InvalidStartTime)
@@ -75,6 +578,7 @@ impl Event {
             // lol literally the first time ive used this syntax
             Ok(Event {
                 start: new_start,
+                modified: now(),
                 ..self
             })
         } else {
@@ -93,6 +597,7 @@ impl Event {
             // lol literally the first time ive used this syntax
             Ok(Event {
                 start: new_start,
+                modified: now(),
                 ..self
             })
         } else {
@@ -101,6 +606,21 @@ impl Event {
         }
     }
 
+    /// Set/Change the date and time of the start field, never failing.
+    ///
+    /// If `start` is not before the current end time, the end is moved to
+    /// one second after `start` instead of returning an error like
+    /// [`Event::set_start`] does
+    pub fn set_start_clamped(self, start: NaiveDateTime) -> Self {
+        let min_end = start + chrono::Duration::seconds(MIN_DURATION_SECONDS);
+        Event {
+            start,
+            end: self.end.max(min_end),
+            modified: now(),
+            ..self
+        }
+    }
+
     /// Set/Change the date and time of the end field
     pub fn set_end(self, end: NaiveDateTime) -> Result<Self, EventError> {
         // check how many seconds from the end time the start time is, if the value
@@ -108,12 +628,31 @@ impl Event {
         // results in an InvalidEndTime error, on success returns new end time
         if Event::start_end_times_valid(&self.start, &end) {
             // previous end time is overwritten
-            Ok(Event { end, ..self })
+            Ok(Event {
+                end,
+                modified: now(),
+                ..self
+            })
         } else {
             Err(EventError::InvalidEndTime)
         }
     }
 
+    /// Set/Change the date and time of the end field, never failing.
+    ///
+    /// If `end` is not after the current start time, the start is moved to
+    /// one second before `end` instead of returning an error like
+    /// [`Event::set_end`] does
+    pub fn set_end_clamped(self, end: NaiveDateTime) -> Self {
+        let max_start = end - chrono::Duration::seconds(MIN_DURATION_SECONDS);
+        Event {
+            start: self.start.min(max_start),
+            end,
+            modified: now(),
+            ..self
+        }
+    }
+
     /// Set/Change the time of the end field
     pub fn set_end_time(self, end: NaiveTime) -> Result<Self, EventError> {
         // check how many seconds from the end time the start time is, if the value
@@ -124,6 +663,7 @@ impl Event {
             // previous end time is overwritten
             Ok(Event {
                 end: new_end,
+                modified: now(),
                 ..self
             })
         } else {
@@ -141,6 +681,7 @@ impl Event {
             // previous end time is overwritten
             Ok(Event {
                 end: new_end,
+                modified: now(),
                 ..self
             })
         } else {
@@ -148,12 +689,784 @@ impl Event {
         }
     }
 
+    /// Set/Change the date and time of the start field in place, the event
+    /// is left unchanged if `start` is not before the end
+    pub fn set_start_mut(&mut self, start: NaiveDateTime) -> Result<(), EventError> {
+        if !Event::start_end_times_valid(&start, &self.end) {
+            return Err(EventError::InvalidStartTime);
+        }
+        self.start = start;
+        self.modified = now();
+        Ok(())
+    }
+
+    /// Set/Change the date and time of the end field in place, the event
+    /// is left unchanged if `end` is not after the start
+    pub fn set_end_mut(&mut self, end: NaiveDateTime) -> Result<(), EventError> {
+        if !Event::start_end_times_valid(&self.start, &end) {
+            return Err(EventError::InvalidEndTime);
+        }
+        self.end = end;
+        self.modified = now();
+        Ok(())
+    }
+
     /// Change the name of an event
     pub fn set_name(&mut self, new_name: String) {
         self.name = new_name;
+        self.modified = now();
+    }
+
+    /// Set a custom property (e.g. a meeting url or room code), returning
+    /// the previous value if the key was already set. Keys are exported as
+    /// `X-<key>`, so they may only hold ASCII letters, digits and `-`
+    pub fn set_property(
+        &mut self,
+        key: String,
+        value: String,
+    ) -> Result<Option<String>, EventError> {
+        if !ics::is_property_key(&key) {
+            return Err(EventError::InvalidPropertyKey(key));
+        }
+        self.modified = now();
+        Ok(self.properties.insert(key, value))
+    }
+
+    /// Set the time zone the start and end times are in, or make them
+    /// floating times with None
+    pub fn set_zone(&mut self, zone: Option<Tz>) {
+        self.zone = zone.map(|tz| tz.name().to_string());
+        self.modified = now();
+    }
+
+    /// Set the color the event is displayed in from `#rgb`, `#rrggbb` or a
+    /// CSS color name, see [`parse_color`], or remove it with None
+    pub fn set_color(&mut self, color: Option<&str>) -> Result<(), EventError> {
+        self.color = match color {
+            Some(s) => Some(parse_color(s).ok_or_else(|| EventError::InvalidColor(s.into()))?),
+            None => None,
+        };
+        self.modified = now();
+        Ok(())
+    }
+
+    /// Set the color the event is displayed in
+    pub fn with_color(self, color: Color) -> Self {
+        Event {
+            color: Some(color),
+            modified: now(),
+            ..self
+        }
+    }
+
+    /// returns the color the event is displayed in, if any
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    /// Make the event repeat, or stop it repeating with None. Fails for a
+    /// monthly rule on a weekday position outside `1..=5` or `-5..=-1`
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) -> Result<(), EventError> {
+        if let Some(RecurrenceRule::Monthly {
+            by_set_pos: Some((pos, _)),
+            ..
+        }) = recurrence.as_ref().map(|rec| &rec.rule)
+        {
+            if !recur::is_valid_set_pos(*pos) {
+                return Err(EventError::InvalidSetPos(*pos));
+            }
+        }
+
+        self.recurrence = recurrence;
+        self.modified = now();
+        Ok(())
+    }
+
+    /// Replace the occurrence of a recurring event originally starting on
+    /// `original_date` with `replacement`, e.g. to move a single meeting of
+    /// a series to another day. Expansion yields the replacement's name and
+    /// times instead of the regular occurrence, overriding the same date
+    /// again replaces the previous override
+    pub fn override_occurrence(&mut self, original_date: NaiveDate, replacement: Event) {
+        self.overrides.insert(original_date, replacement);
+        self.modified = now();
+    }
+
+    /// returns the overridden occurrences keyed by their original date
+    pub fn overrides(&self) -> &BTreeMap<NaiveDate, Event> {
+        &self.overrides
+    }
+
+    /// Add several tags at once, duplicates are only kept once
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = String>) -> Self {
+        self.tags.extend(tags);
+        self.modified = now();
+        self
+    }
+
+    /// Add a tag to the event, returning false if it already had the tag
+    pub fn add_tag(&mut self, tag: String) -> bool {
+        let added = self.tags.insert(tag);
+        if added {
+            self.modified = now();
+        }
+        added
+    }
+
+    /// Remove a tag from the event, returning false if it did not have the tag
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let removed = self.tags.remove(tag);
+        if removed {
+            self.modified = now();
+        }
+        removed
+    }
+
+    /// Remove a custom property, returning its value if it was set
+    pub fn remove_property(&mut self, key: &str) -> Option<String> {
+        let old = self.properties.remove(key);
+        if old.is_some() {
+            self.modified = now();
+        }
+        old
+    }
+
+    /// Apply all set fields of `patch` at once, the resulting start and end
+    /// times are only validated after every field has been applied so a patch
+    /// can move an event to an entirely different time
+    pub fn apply_patch(self, patch: EventPatch) -> Result<Self, EventError> {
+        let start = patch.start.unwrap_or(self.start);
+        let end = patch.end.unwrap_or(self.end);
+
+        if !Event::start_end_times_valid(&start, &end) {
+            return match patch.start {
+                Some(_) => Err(EventError::InvalidStartTime),
+                None => Err(EventError::InvalidEndTime),
+            };
+        }
+
+        Ok(Event {
+            start,
+            end,
+            name: patch.name.unwrap_or(self.name),
+            modified: now(),
+            ..self
+        })
+    }
+
+    /// Expand the event into the concrete occurrences overlapping the window
+    /// from start to end, in chronological order. Occurrences of a recurring
+    /// event get their own id, have no recurrence of their own and link back
+    /// to this event through [`Event::master_id`]. An event without a
+    /// recurrence yields only itself if it overlaps the window.
+    ///
+    /// Occurrences replaced with [`Event::override_occurrence`] are yielded
+    /// in place of the original, at the position of the original in the
+    /// series, and only if the replacement itself overlaps the window.
+    ///
+    /// Fails with [`RecurError::TooMany`] rather than allocating more than
+    /// [`MAX_OCCURRENCES`] events, use [`Event::occurrences_iter`] to walk
+    /// larger windows lazily
+    pub fn occurrences_between(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Result<Vec<Event>, RecurError> {
+        let occs: Vec<Event> = self
+            .occurrences_iter(start, end)
+            .take(MAX_OCCURRENCES + 1)
+            .collect();
+
+        if occs.len() > MAX_OCCURRENCES {
+            return Err(RecurError::TooMany);
+        }
+        Ok(occs)
+    }
+
+    /// lazily yield the same occurrences as [`Event::occurrences_between`],
+    /// without any limit on their number
+    pub fn occurrences_iter(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> impl Iterator<Item = Event> + '_ {
+        let starts: Box<dyn Iterator<Item = NaiveDateTime>> = match &self.recurrence {
+            Some(rec) => Box::new(rec.starts(self.start)),
+            None => Box::new(std::iter::once(self.start)),
+        };
+
+        let duration = self.end - self.start;
+        let occ_end = move |occ_start: NaiveDateTime| match self.is_open_ended() {
+            true => OPEN_END,
+            false => add_saturating(occ_start, duration),
+        };
+        let in_window = move |occ_start: NaiveDateTime, occ_end: NaiveDateTime| {
+            occ_start < end && occ_end > start
+        };
+        let override_of = move |occ_start: NaiveDateTime| match self.recurrence {
+            Some(_) => self.overrides.get(&occ_start.date()),
+            None => None,
+        };
+
+        // an override moved into the window may come from a later occurrence
+        let scan_end = self
+            .overrides
+            .iter()
+            .filter(|(_, occ)| in_window(occ.start, occ.end))
+            .map(|(date, _)| date.and_time(day_end()))
+            .fold(end, NaiveDateTime::max);
+
+        starts
+            .take_while(move |occ_start| *occ_start < scan_end)
+            .filter(move |occ_start| match override_of(*occ_start) {
+                Some(occ) => in_window(occ.start, occ.end),
+                None => in_window(*occ_start, occ_end(*occ_start)),
+            })
+            .map(
+                move |occ_start| match (&self.recurrence, override_of(occ_start)) {
+                    (None, _) => self.clone(),
+                    (Some(_), Some(occ)) => Event {
+                        id: Uuid::new_v4(),
+                        recurrence: None,
+                        master_id: Some(self.id),
+                        overrides: BTreeMap::new(),
+                        ..occ.clone()
+                    },
+                    (Some(_), None) => Event {
+                        start: occ_start,
+                        end: occ_end(occ_start),
+                        id: Uuid::new_v4(),
+                        recurrence: None,
+                        master_id: Some(self.id),
+                        overrides: BTreeMap::new(),
+                        ..self.clone()
+                    },
+                },
+            )
+    }
+
+    /// split the event into one (date, start, end) segment per day it
+    /// touches, see [`split_range_by_day`]. Open-ended events only yield
+    /// the segment for their start date
+    pub fn split_by_day(&self) -> Vec<(NaiveDate, NaiveDateTime, NaiveDateTime)> {
+        if self.is_open_ended() {
+            return split_range_by_day(self.start, self.start.date().and_time(day_end()));
+        }
+        split_range_by_day(self.start, self.end)
+    }
+
+    /// Snap an event that starts at midnight and ends within a few minutes of
+    /// the end of a day (or exactly at a later midnight) to exact all day
+    /// bounds, so it is reported by [`Event::is_all_day`]. Events that do not
+    /// look like all day events are returned unchanged
+    pub fn normalize_all_day(self) -> Self {
+        if self.start.time() != day_start() || self.is_all_day() || self.is_open_ended() {
+            return self;
+        }
+
+        // an event ending at midnight already ends with its last full day
+        let end_date = self.last_date();
+        if end_date == self.end.date() {
+            let until_day_end = NaiveDateTime::new(end_date, day_end()) - self.end;
+            if until_day_end > chrono::Duration::minutes(ALL_DAY_TOLERANCE_MINUTES) {
+                return self;
+            }
+        }
+
+        Event {
+            end: NaiveDateTime::new(end_date, day_end()),
+            modified: now(),
+            ..self
+        }
+    }
+
+    /// returns the (top, height) of the part of the event on `date` as
+    /// fractions of the day, or None if the event does not touch that day.
+    /// An end time of [`day_end`] is treated as running until midnight so
+    /// all day events fill the whole day
+    pub fn day_position(&self, date: NaiveDate) -> Option<(f32, f32)> {
+        const SECS_PER_DAY: f32 = 86_400.0;
+
+        let midnight = NaiveDateTime::new(date, day_start());
+        let next_midnight = midnight + chrono::Duration::days(1);
+
+        let end = if self.end.time() == day_end() {
+            self.end + chrono::Duration::seconds(1)
+        } else {
+            self.end
+        };
+
+        let top = self.start.max(midnight);
+        let bottom = end.min(next_midnight);
+        if bottom <= top {
+            return None;
+        }
+
+        let offset = (top - midnight).num_seconds() as f32;
+        let length = (bottom - top).num_seconds() as f32;
+        Some((offset / SECS_PER_DAY, length / SECS_PER_DAY))
+    }
+
+    /// Apply a partial JSON object such as `{"name": "X"}` to the event, for
+    /// web APIs that send partial bodies. The keys `name`, `start` and `end`
+    /// are applied like an [`EventPatch`] (times use the same format as
+    /// [`Event::serialize`]), null values and unknown keys are ignored
+    pub fn merge_json(self, patch: &serde_json::Value) -> Result<Self, EventError> {
+        let obj = patch
+            .as_object()
+            .ok_or_else(|| EventError::InvalidPatch("expected a JSON object".into()))?;
+
+        let field = |key: &str| obj.get(key).filter(|value| !value.is_null());
+        let time = |key: &str| {
+            field(key)
+                .map(|value| {
+                    NaiveDateTime::deserialize(value)
+                        .map_err(|e| EventError::InvalidPatch(format!("{key}: {e}")))
+                })
+                .transpose()
+        };
+
+        let name = field("name")
+            .map(|value| {
+                value
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| EventError::InvalidPatch("name: expected a string".into()))
+            })
+            .transpose()?;
+
+        self.apply_patch(EventPatch {
+            name,
+            start: time("start")?,
+            end: time("end")?,
+        })
+    }
+
+    /// returns how long until the event starts, negative if the
+    /// event has already started
+    pub fn time_until(&self, now: NaiveDateTime) -> chrono::Duration {
+        self.start.signed_duration_since(now)
+    }
+
+    /// returns a human readable version of [`Event::time_until`] using the
+    /// largest whole unit, e.g. "in 2 hours" or "5 minutes ago"
+    pub fn time_until_human(&self, now: NaiveDateTime) -> String {
+        let until = self.time_until(now);
+        let abs = until.abs();
+
+        let (amount, unit) = if abs.num_days() > 0 {
+            (abs.num_days(), "day")
+        } else if abs.num_hours() > 0 {
+            (abs.num_hours(), "hour")
+        } else if abs.num_minutes() > 0 {
+            (abs.num_minutes(), "minute")
+        } else {
+            return String::from("now");
+        };
+
+        let plural = if amount == 1 { "" } else { "s" };
+        if until > chrono::Duration::zero() {
+            format!("in {amount} {unit}{plural}")
+        } else {
+            format!("{amount} {unit}{plural} ago")
+        }
+    }
+
+    /// Move both the start and end time by `by`, keeping the duration.
+    /// Open-ended events stay open-ended, and times saturate at the earliest
+    /// and latest representable times instead of overflowing
+    pub fn shift(self, by: chrono::Duration) -> Self {
+        let end = match self.is_open_ended() {
+            true => OPEN_END,
+            false => add_saturating(self.end, by),
+        };
+        Event {
+            start: add_saturating(self.start, by),
+            end,
+            modified: now(),
+            ..self
+        }
+    }
+
+    /// Combine with `other` into one event spanning from the earlier start to
+    /// the later end, keeping this event's name, id and other fields. All
+    /// day events on consecutive dates touch, see [`Event::overlaps`]
+    pub fn join(self, other: Event) -> Result<Self, EventError> {
+        if self.start > other.covered_until() || other.start > self.covered_until() {
+            return Err(EventError::Disjoint);
+        }
+
+        Ok(Event {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            modified: now(),
+            ..self
+        })
+    }
+
+    /// returns the earliest start time, not before the current one, at which
+    /// the event keeps its duration and is at least `gap` away from every
+    /// event in `others`. Events with the same id as this one are ignored
+    pub fn suggest_non_overlapping(
+        &self,
+        others: &[&Event],
+        gap: chrono::Duration,
+    ) -> NaiveDateTime {
+        let duration = self.end - self.start;
+        let mut others: Vec<&Event> = others.iter().filter(|o| o.id != self.id).copied().collect();
+        others.sort_by(|a, b| Event::cmp_chronological(a, b));
+
+        // once an event starts after the candidate's end so do all later
+        // ones, so a single pass in start order is enough
+        others.into_iter().fold(self.start, |candidate, other| {
+            let candidate_end = add_saturating(add_saturating(candidate, duration), gap);
+            if candidate < add_saturating(other.end, gap) && other.start < candidate_end {
+                add_saturating(other.end, gap)
+            } else {
+                candidate
+            }
+        })
+    }
+
+    /// Round the start time down and the end time up to the nearest
+    /// multiple of `granularity`, e.g. to tidy imported times to 15 minutes
+    pub fn round_times(self, granularity: chrono::Duration) -> Result<Self, EventError> {
+        let start = self
+            .start
+            .duration_trunc(granularity)
+            .map_err(|_| EventError::InvalidDuration)?;
+
+        // an open end has nothing to round
+        let end = match self.is_open_ended() {
+            true => OPEN_END,
+            false => {
+                let end = self
+                    .end
+                    .duration_trunc(granularity)
+                    .map_err(|_| EventError::InvalidDuration)?;
+                match end == self.end {
+                    true => end,
+                    false => end
+                        .checked_add_signed(granularity)
+                        .ok_or(EventError::InvalidDuration)?,
+                }
+            }
+        };
+
+        if Event::start_end_times_valid(&start, &end) {
+            Ok(Event {
+                start,
+                end,
+                modified: now(),
+                ..self
+            })
+        } else {
+            Err(EventError::InvalidEndTime)
+        }
     }
 
     pub fn serialize(&self) -> String {
         serde_json::to_string(&self).unwrap()
     }
+
+    /// Serialize the event without the `modified` timestamp and without any
+    /// null or empty fields other than the `end` of an open-ended event. The
+    /// remaining fields keep the order of [`Event::serialize`]. Events read
+    /// back from this have a `modified` time of the unix epoch
+    pub fn serialize_compact(&self) -> String {
+        serde_json::to_string(&CompactEvent {
+            start: self.start,
+            end: self.end,
+            name: &self.name,
+            id: self.id,
+            offset: self.offset,
+            properties: &self.properties,
+            tags: &self.tags,
+            recurrence: self.recurrence.as_ref(),
+            master_id: self.master_id,
+            zone: self.zone.as_deref(),
+            overrides: &self.overrides,
+            color: self.color,
+        })
+        .unwrap()
+    }
+
+    /// Parse a single VEVENT block (without the surrounding VCALENDAR).
+    ///
+    /// DTSTART is required. Without DTEND the end is taken from DURATION, an
+    /// all day event lasts one day and any other event is open-ended, see
+    /// [`Event::new_open_ended`]. DATE values produce an all day event
+    /// and the exclusive DTEND date is mapped to the end of the previous day.
+    /// DATE-TIME values with an explicit UTC offset keep their local time and
+    /// the offset of DTSTART is stored on the event. CATEGORIES become tags and
+    /// `X-<key>` properties are
+    /// stored as custom properties under `<key>`. A UID that is not a valid UUID is replaced by a freshly generated id.
+    /// An RRULE makes the event recurring, see [`EventCalendar::to_ics`] for
+    /// the rules that are read.
+    /// Folded content lines are joined before parsing. Leap seconds such as
+    /// `235960` are kept as chrono leap seconds and written back unchanged.
+    pub fn from_ics_vevent(block: &str) -> Result<Self, IcsError> {
+        Self::from_ics_component(block).map(|(evt, _)| evt)
+    }
+
+    /// Parse a VEVENT block like [`Event::from_ics_vevent`], also returning
+    /// the date of its RECURRENCE-ID if it overrides an occurrence of the
+    /// recurring event with the same UID
+    pub(crate) fn from_ics_component(block: &str) -> Result<(Self, Option<NaiveDate>), IcsError> {
+        let unfolded = ics::unfold_lines(block);
+        let mut lines = unfolded.iter().map(String::as_str);
+
+        if lines.next() != Some("BEGIN:VEVENT") {
+            return Err(IcsError::NotAnEvent);
+        }
+
+        let mut start = None;
+        let mut end = None;
+        let mut duration = None;
+        let mut all_day = false;
+        let mut rrule = None;
+        let mut recurrence_id = None;
+        let mut offset = None;
+        let mut end_offset = None;
+        let mut name = String::new();
+        let mut id = None;
+        let mut modified = None;
+        let mut properties = BTreeMap::new();
+        let mut tags = BTreeSet::new();
+        let mut finished = false;
+        // depth of nested components (e.g. VALARM) whose properties are skipped
+        let mut nested = 0usize;
+
+        for line in lines {
+            if finished {
+                return Err(IcsError::NotAnEvent);
+            }
+
+            let prop = ics::Property::parse(line)?;
+            match (prop.name, nested) {
+                ("BEGIN", _) => nested += 1,
+                ("END", 0) if prop.value == "VEVENT" => finished = true,
+                ("END", 0) => return Err(IcsError::NotAnEvent),
+                ("END", _) => nested -= 1,
+                (_, 1..) => {}
+                ("DTSTART", _) if prop.is_date() => {
+                    start = Some(NaiveDateTime::new(
+                        ics::parse_date(prop.value)?,
+                        day_start(),
+                    ));
+                    all_day = true;
+                }
+                ("DTSTART", _) => {
+                    let (dt, off) = ics::parse_date_time_offset(prop.value)?;
+                    start = Some(dt);
+                    offset = off;
+                }
+                ("DTEND", _) if prop.is_date() => {
+                    // DTEND dates are exclusive, so the event ends on the day before
+                    let date = ics::parse_date(prop.value)?;
+                    let date = date.pred_opt().unwrap_or(date);
+                    end = Some(NaiveDateTime::new(date, day_end()))
+                }
+                ("DTEND", _) => {
+                    let (dt, off) = ics::parse_date_time_offset(prop.value)?;
+                    end = Some(dt);
+                    end_offset = off;
+                }
+                ("DURATION", _) => duration = Some(ics::parse_duration(prop.value)?),
+                ("RRULE", _) => rrule = Some(prop.value),
+                ("RECURRENCE-ID", _) if prop.is_date() => {
+                    recurrence_id = Some(ics::parse_date(prop.value)?)
+                }
+                ("RECURRENCE-ID", _) => {
+                    recurrence_id = Some(ics::parse_date_time(prop.value)?.date())
+                }
+                ("SUMMARY", _) => name = ics::unescape_text(prop.value),
+                ("UID", _) => id = Uuid::parse_str(prop.value).ok(),
+                ("LAST-MODIFIED", _) => modified = Some(ics::parse_date_time(prop.value)?),
+                ("CATEGORIES", _) => tags.extend(ics::split_list(prop.value)),
+                (name, _) if name.starts_with("X-") && ics::is_property_key(&name[2..]) => {
+                    properties.insert(name[2..].to_string(), ics::unescape_text(prop.value));
+                }
+                _ => {}
+            }
+        }
+
+        if !finished {
+            return Err(IcsError::NotAnEvent);
+        }
+
+        let start = start.ok_or(IcsError::MissingProperty("DTSTART"))?;
+        // without DTEND RFC 5545 takes the end from DURATION, or else an all
+        // day event lasts one day. Other events are read as having no known end
+        let mut end = match (end, duration) {
+            (Some(end), _) => end,
+            (None, Some(duration)) => {
+                let end = start
+                    .checked_add_signed(duration)
+                    .ok_or(EventError::InvalidEndTime)?;
+                match all_day {
+                    // like a DATE valued DTEND the day after is exclusive
+                    true => {
+                        NaiveDateTime::new(end.date().pred_opt().unwrap_or(end.date()), day_end())
+                    }
+                    false => end,
+                }
+            }
+            (None, None) if all_day => NaiveDateTime::new(start.date(), day_end()),
+            (None, None) => OPEN_END,
+        };
+        // express the end time in the same offset as the start time
+        if let (Some(start_off), Some(end_off)) = (offset, end_offset) {
+            let diff = start_off.local_minus_utc() - end_off.local_minus_utc();
+            end += chrono::Duration::seconds(diff.into());
+        }
+        if !Event::start_end_times_valid(&start, &end) {
+            return Err(EventError::InvalidEndTime.into());
+        }
+        let recurrence = rrule
+            .map(|rule| ics::parse_rrule(rule, offset))
+            .transpose()?;
+
+        let evt = Self {
+            start,
+            end,
+            name,
+            id: id.unwrap_or_else(Uuid::new_v4),
+            modified: modified.unwrap_or_else(now),
+            offset: offset.map(|o| o.local_minus_utc()),
+            properties,
+            tags,
+            recurrence,
+            master_id: None,
+            zone: None,
+            overrides: BTreeMap::new(),
+            color: None,
+        };
+        Ok((evt, recurrence_id))
+    }
+
+    /// attach an override read from an iCalendar document, unlike
+    /// [`Event::override_occurrence`] this keeps the `modified` time. The
+    /// override shares the UID of this event, so it is given an id of its own
+    pub(crate) fn attach_ics_override(&mut self, original_date: NaiveDate, mut occ: Event) {
+        occ.id = Uuid::new_v4();
+        self.overrides.insert(original_date, occ);
+    }
+
+    /// returns the name with RFC 5545 TEXT escaping applied to backslashes,
+    /// commas, semicolons and newlines, as written to SUMMARY
+    pub fn ics_summary_escaped(&self) -> String {
+        ics::escape_text(&self.name)
+    }
+
+    /// Export the event as a single VEVENT block with CRLF line endings.
+    ///
+    /// Events spanning whole days are written with DATE values, all other
+    /// times are written as DATE-TIME values carrying the event's offset,
+    /// or as floating times if it has none. Tags are written as CATEGORIES
+    /// and custom properties are written as `X-<key>` properties. The name
+    /// is escaped according to RFC 5545. A recurring event is written with
+    /// an RRULE, its overridden occurrences are only written by
+    /// [`EventCalendar::to_ics`].
+    pub fn to_ics_vevent(&self) -> String {
+        // occurrences share the UID of their recurring event
+        self.to_ics_with_uid(self.master_id.unwrap_or(self.id), None)
+    }
+
+    /// Export the overridden occurrences of a recurring event as VEVENT
+    /// blocks sharing its UID, each with the RECURRENCE-ID of the occurrence
+    /// it replaces
+    pub(crate) fn to_ics_overrides(&self) -> impl Iterator<Item = String> + '_ {
+        self.overrides.iter().map(|(date, occ)| {
+            let recurrence_id = match self.is_all_day() {
+                true => format!("RECURRENCE-ID;VALUE=DATE:{}", ics::format_date(date)),
+                false => format!(
+                    "RECURRENCE-ID:{}",
+                    ics::format_date_time_offset(&date.and_time(self.start.time()), self.offset())
+                ),
+            };
+            occ.to_ics_with_uid(self.id, Some(recurrence_id))
+        })
+    }
+
+    /// the UNTIL of an RRULE written in the form of DTSTART, UTC when the
+    /// event has an offset as RFC 5545 requires
+    fn ics_until(&self, until: NaiveDateTime) -> String {
+        match (self.is_all_day(), self.offset()) {
+            (true, _) => ics::format_date(&until.date()),
+            (false, Some(offset)) => ics::format_date_time_offset(
+                &(until - chrono::Duration::seconds(offset.local_minus_utc().into())),
+                FixedOffset::east_opt(0),
+            ),
+            (false, None) => ics::format_date_time(&until),
+        }
+    }
+
+    /// Export the event as a VEVENT block with the given UID and, for an
+    /// overridden occurrence, its RECURRENCE-ID line. An occurrence written
+    /// under a UID of its own is linked to its recurring event with
+    /// RELATED-TO
+    pub(crate) fn to_ics_with_uid(&self, uid: Uuid, recurrence_id: Option<String>) -> String {
+        let (dtstart, dtend) = if self.is_all_day() {
+            // DTEND dates are exclusive, so the event ends on the next day
+            let end = self.end.date().succ_opt().unwrap_or(self.end.date());
+            (
+                format!(
+                    "DTSTART;VALUE=DATE:{}",
+                    ics::format_date(&self.start.date())
+                ),
+                Some(format!("DTEND;VALUE=DATE:{}", ics::format_date(&end))),
+            )
+        } else {
+            (
+                format!(
+                    "DTSTART:{}",
+                    ics::format_date_time_offset(&self.start, self.offset())
+                ),
+                // open-ended events are written without an end
+                self.fixed_end().map(|end| {
+                    format!(
+                        "DTEND:{}",
+                        ics::format_date_time_offset(&end, self.offset())
+                    )
+                }),
+            )
+        };
+
+        let stamp = format!("{}Z", ics::format_date_time(&self.modified));
+
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{uid}"),
+            format!("DTSTAMP:{stamp}"),
+            format!("LAST-MODIFIED:{stamp}"),
+            dtstart,
+        ];
+        lines.extend(dtend);
+        lines.push(format!("SUMMARY:{}", self.ics_summary_escaped()));
+
+        lines.extend(recurrence_id);
+        if let Some(rec) = &self.recurrence {
+            let until = rec.until.map(|until| self.ics_until(until));
+            lines.push(format!("RRULE:{}", ics::format_rrule(rec, until)));
+        }
+        if let Some(master) = self.master_id.filter(|master| *master != uid) {
+            lines.push(format!("RELATED-TO:{master}"));
+        }
+
+        if !self.tags.is_empty() {
+            let tags: Vec<_> = self.tags.iter().map(|t| ics::escape_text(t)).collect();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+
+        // custom properties are written as X- properties
+        lines.extend(
+            self.properties
+                .iter()
+                .map(|(key, value)| format!("X-{key}:{}", ics::escape_text(value))),
+        );
+        lines.push("END:VEVENT".to_string());
+
+        lines.iter().map(|line| format!("{line}\r\n")).collect()
+    }
 }
@@ -0,0 +1,379 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Weekday};
+
+use super::{day_end, IcsError};
+use crate::recur::{Recurrence, RecurrenceRule};
+
+/// format used by iCalendar DATE-TIME values, e.g. 20230101T120000
+const ICS_DATE_TIME_FMT: &str = "%Y%m%dT%H%M%S";
+
+/// format used by iCalendar DATE values, e.g. 20230101
+const ICS_DATE_FMT: &str = "%Y%m%d";
+
+/// A single content line of an iCalendar object split into its parts,
+/// e.g. `DTSTART;VALUE=DATE:20230101`
+pub(crate) struct Property<'a> {
+    pub name: &'a str,
+    pub params: Vec<&'a str>,
+    pub value: &'a str,
+}
+
+impl<'a> Property<'a> {
+    /// split a content line into its name, parameters and value
+    pub fn parse(line: &'a str) -> Result<Self, IcsError> {
+        let (head, value) = line
+            .split_once(':')
+            .ok_or_else(|| IcsError::MalformedLine(line.to_string()))?;
+
+        let mut parts = head.split(';');
+        // split always yields at least one item
+        let name = parts.next().unwrap();
+        if name.is_empty() {
+            return Err(IcsError::MalformedLine(line.to_string()));
+        }
+
+        Ok(Self {
+            name,
+            params: parts.collect(),
+            value,
+        })
+    }
+
+    /// returns true if the property has the parameter VALUE=DATE
+    pub fn is_date(&self) -> bool {
+        self.params
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case("VALUE=DATE"))
+    }
+}
+
+/// split text into content lines, joining folded lines back together. A
+/// line starting with a space or tab continues the previous one, with that
+/// single whitespace character removed (RFC 5545 section 3.1)
+pub(crate) fn unfold_lines(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines().map(|l| l.trim_end_matches('\r')) {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(prev)) => prev.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines.retain(|l| !l.is_empty());
+    lines
+}
+
+/// format a DATE-TIME value without a timezone (floating time)
+pub(crate) fn format_date_time(dt: &NaiveDateTime) -> String {
+    dt.format(ICS_DATE_TIME_FMT).to_string()
+}
+
+/// format a DATE-TIME value with an optional UTC offset, a zero offset is
+/// written as `Z` and any other offset as `+HHMM`/`-HHMM`
+pub(crate) fn format_date_time_offset(dt: &NaiveDateTime, offset: Option<FixedOffset>) -> String {
+    let mut value = format_date_time(dt);
+
+    match offset.map(|o| o.local_minus_utc()) {
+        None => {}
+        Some(0) => value.push('Z'),
+        Some(secs) => {
+            let sign = if secs < 0 { '-' } else { '+' };
+            let mins = secs.abs() / 60;
+            value.push_str(&format!("{sign}{:02}{:02}", mins / 60, mins % 60));
+        }
+    }
+
+    value
+}
+
+/// format a DATE value
+pub(crate) fn format_date(date: &NaiveDate) -> String {
+    date.format(ICS_DATE_FMT).to_string()
+}
+
+/// parse an iCalendar DATE-TIME value, ignoring any UTC offset
+pub(crate) fn parse_date_time(value: &str) -> Result<NaiveDateTime, IcsError> {
+    parse_date_time_offset(value).map(|(dt, _)| dt)
+}
+
+/// parse an iCalendar DATE-TIME value into its local time and UTC offset,
+/// floating times without a trailing `Z` or `+HHMM`/`-HHMM` have no offset
+pub(crate) fn parse_date_time_offset(
+    value: &str,
+) -> Result<(NaiveDateTime, Option<FixedOffset>), IcsError> {
+    let invalid = || IcsError::InvalidDateTime(value.to_string());
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        let dt = NaiveDateTime::parse_from_str(utc, ICS_DATE_TIME_FMT).map_err(|_| invalid())?;
+        return Ok((dt, FixedOffset::east_opt(0)));
+    }
+
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, ICS_DATE_TIME_FMT) {
+        return Ok((dt, None));
+    }
+
+    DateTime::parse_from_str(value, "%Y%m%dT%H%M%S%z")
+        .map(|dt| (dt.naive_local(), Some(*dt.offset())))
+        .map_err(|_| invalid())
+}
+
+/// parse an iCalendar DATE value
+pub(crate) fn parse_date(value: &str) -> Result<NaiveDate, IcsError> {
+    NaiveDate::parse_from_str(value, ICS_DATE_FMT)
+        .map_err(|_| IcsError::InvalidDateTime(value.to_string()))
+}
+
+/// parse an iCalendar DURATION value, e.g. `PT1H30M`, `P1D` or `-P2W`
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, IcsError> {
+    let invalid = || IcsError::InvalidDuration(value.to_string());
+
+    let (negative, rest) = match value.as_bytes().first() {
+        Some(b'-') => (true, &value[1..]),
+        Some(b'+') => (false, &value[1..]),
+        _ => (false, value),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    let mut any = false;
+
+    for c in rest.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'T' if !in_time && number.is_empty() => {
+                in_time = true;
+                continue;
+            }
+            'W' if !in_time => Duration::weeks(1),
+            'D' if !in_time => Duration::days(1),
+            'H' if in_time => Duration::hours(1),
+            'M' if in_time => Duration::minutes(1),
+            'S' if in_time => Duration::seconds(1),
+            _ => return Err(invalid()),
+        };
+
+        let n: i32 = std::mem::take(&mut number).parse().map_err(|_| invalid())?;
+        total = total.checked_add(&(unit * n)).ok_or_else(invalid)?;
+        any = true;
+    }
+
+    if !any || !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(if negative { -total } else { total })
+}
+
+/// returns true if `key` can follow `X-` in a property name, i.e. it is
+/// made of ASCII letters, digits and `-` (RFC 5545 section 3.1)
+pub(crate) fn is_property_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// the two letter iCalendar name of a weekday, e.g. `MO`
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+/// parse a two letter iCalendar weekday, see [`weekday_code`]
+fn parse_weekday(code: &str) -> Option<Weekday> {
+    [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+    .into_iter()
+    .find(|day| weekday_code(*day).eq_ignore_ascii_case(code))
+}
+
+/// format a recurrence as an RRULE value, e.g. `FREQ=WEEKLY;BYDAY=MO,WE`.
+/// `until` is the UNTIL value already written in the form of DTSTART
+pub(crate) fn format_rrule(rec: &Recurrence, until: Option<String>) -> String {
+    let (freq, interval, by_day) = match &rec.rule {
+        RecurrenceRule::Daily { interval } => ("DAILY", interval, None),
+        RecurrenceRule::Weekly { interval, by_day } => {
+            let days: Vec<_> = by_day.iter().map(|day| weekday_code(*day)).collect();
+            (
+                "WEEKLY",
+                interval,
+                (!days.is_empty()).then(|| days.join(",")),
+            )
+        }
+        RecurrenceRule::Monthly {
+            interval,
+            by_set_pos,
+        } => (
+            "MONTHLY",
+            interval,
+            by_set_pos.map(|(pos, day)| format!("{pos}{}", weekday_code(day))),
+        ),
+    };
+
+    let mut parts = vec![format!("FREQ={freq}")];
+    if *interval > 1 {
+        parts.push(format!("INTERVAL={interval}"));
+    }
+    parts.extend(by_day.map(|days| format!("BYDAY={days}")));
+    parts.extend(rec.count.map(|count| format!("COUNT={count}")));
+    parts.extend(until.map(|until| format!("UNTIL={until}")));
+    parts.join(";")
+}
+
+/// parse an RRULE value repeating daily, weekly (optionally on BYDAY
+/// weekdays) or monthly (optionally on the nth BYDAY weekday), as written
+/// by [`format_rrule`]. A DATE valued UNTIL covers the whole date and a UTC
+/// UNTIL is moved to `offset`, the offset of DTSTART
+pub(crate) fn parse_rrule(
+    value: &str,
+    offset: Option<FixedOffset>,
+) -> Result<Recurrence, IcsError> {
+    let unsupported = || IcsError::UnsupportedRecurrence(value.to_string());
+
+    let (mut freq, mut interval, mut count, mut until) = (None, 1, None, None);
+    let (mut by_day, mut by_set_pos) = (Vec::new(), None);
+
+    for part in value.split(';') {
+        let (key, val) = part.split_once('=').ok_or_else(unsupported)?;
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => freq = Some(val.to_ascii_uppercase()),
+            "INTERVAL" => interval = val.parse().map_err(|_| unsupported())?,
+            "COUNT" => count = Some(val.parse().map_err(|_| unsupported())?),
+            "UNTIL" if val.len() == 8 => until = Some(parse_date(val)?.and_time(day_end())),
+            "UNTIL" => {
+                let (dt, off) = parse_date_time_offset(val)?;
+                let shift = match (offset, off) {
+                    (Some(to), Some(from)) => to.local_minus_utc() - from.local_minus_utc(),
+                    _ => 0,
+                };
+                until = Some(dt + Duration::seconds(shift.into()));
+            }
+            "BYDAY" => by_day = val.split(',').map(str::to_string).collect(),
+            "BYSETPOS" => by_set_pos = Some(val.parse::<i8>().map_err(|_| unsupported())?),
+            // weeks start on Monday, the default
+            "WKST" if val.eq_ignore_ascii_case("MO") => {}
+            _ => return Err(unsupported()),
+        }
+    }
+
+    let rule = match freq.as_deref() {
+        Some("DAILY") if by_day.is_empty() && by_set_pos.is_none() => {
+            RecurrenceRule::Daily { interval }
+        }
+        Some("WEEKLY") if by_set_pos.is_none() => RecurrenceRule::Weekly {
+            interval,
+            by_day: by_day
+                .iter()
+                .map(|day| parse_weekday(day))
+                .collect::<Option<_>>()
+                .ok_or_else(unsupported)?,
+        },
+        Some("MONTHLY") => {
+            let by_set_pos = match (by_day.as_slice(), by_set_pos) {
+                ([], None) => None,
+                // either BYDAY=3TH or BYDAY=TH;BYSETPOS=3
+                ([day], pos) => {
+                    let split = (day.len().checked_sub(2))
+                        .filter(|_| day.is_ascii())
+                        .ok_or_else(unsupported)?;
+                    let weekday = parse_weekday(&day[split..]).ok_or_else(unsupported)?;
+                    let pos = match (&day[..split], pos) {
+                        ("", Some(pos)) => pos,
+                        (n, None) => n.parse().map_err(|_| unsupported())?,
+                        _ => return Err(unsupported()),
+                    };
+                    if !crate::recur::is_valid_set_pos(pos) {
+                        return Err(unsupported());
+                    }
+                    Some((pos, weekday))
+                }
+                _ => return Err(unsupported()),
+            };
+            RecurrenceRule::Monthly {
+                interval,
+                by_set_pos,
+            }
+        }
+        _ => return Err(unsupported()),
+    };
+
+    Ok(Recurrence { rule, count, until })
+}
+
+/// undo RFC 5545 TEXT escaping (`\\`, `\,`, `\;` and `\n`)
+pub(crate) fn unescape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(escaped) => out.push(escaped),
+            // a lone trailing backslash is kept as is
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// apply RFC 5545 TEXT escaping to backslashes, commas, semicolons and newlines
+pub(crate) fn escape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for c in value.replace("\r\n", "\n").chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// split a comma separated list of TEXT values, unescaping each item
+pub(crate) fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                items.push(unescape_text(&value[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(unescape_text(&value[start..]));
+
+    items.retain(|item| !item.is_empty());
+    items
+}
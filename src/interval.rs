@@ -0,0 +1,191 @@
+use chrono::NaiveDateTime;
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+type Link = Option<Box<Node>>;
+
+/// node of the tree, ordered by (start, id) and augmented with the latest
+/// end time in its subtree
+struct Node {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    id: Uuid,
+    max_end: NaiveDateTime,
+    priority: u64,
+    left: Link,
+    right: Link,
+}
+
+/// heap priority of a node, the bits of the id are mixed (with the
+/// splitmix64 finalizer) so sequential or time ordered ids, e.g. v7 uuids,
+/// still give a balanced tree
+fn priority(id: &Uuid) -> u64 {
+    fn mix(mut x: u64) -> u64 {
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    }
+
+    let (high, low) = id.as_u64_pair();
+    mix(high ^ mix(low))
+}
+
+impl Node {
+    fn new(start: NaiveDateTime, end: NaiveDateTime, id: Uuid) -> Box<Self> {
+        Box::new(Self {
+            start,
+            end,
+            id,
+            max_end: end,
+            priority: priority(&id),
+            left: None,
+            right: None,
+        })
+    }
+
+    fn key(&self) -> (NaiveDateTime, Uuid) {
+        (self.start, self.id)
+    }
+
+    fn update(&mut self) {
+        self.max_end = [&self.left, &self.right]
+            .into_iter()
+            .flatten()
+            .map(|child| child.max_end)
+            .fold(self.end, NaiveDateTime::max);
+    }
+}
+
+/// split into the nodes with a key before `key` and the rest
+fn split(link: Link, key: (NaiveDateTime, Uuid)) -> (Link, Link) {
+    match link {
+        None => (None, None),
+        Some(mut node) if node.key() < key => {
+            let (left, right) = split(node.right.take(), key);
+            node.right = left;
+            node.update();
+            (Some(node), right)
+        }
+        Some(mut node) => {
+            let (left, right) = split(node.left.take(), key);
+            node.left = right;
+            node.update();
+            (left, Some(node))
+        }
+    }
+}
+
+/// join two trees where every key in `left` is before every key in `right`
+fn merge(left: Link, right: Link) -> Link {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut l), Some(mut r)) => {
+            if l.priority > r.priority {
+                l.right = merge(l.right.take(), Some(r));
+                l.update();
+                Some(l)
+            } else {
+                r.left = merge(Some(l), r.left.take());
+                r.update();
+                Some(r)
+            }
+        }
+    }
+}
+
+/// remove the node with `key` from the tree rooted at `link`
+fn remove(link: &mut Link, key: (NaiveDateTime, Uuid)) -> bool {
+    let Some(node) = link else {
+        return false;
+    };
+
+    let found = match key.cmp(&node.key()) {
+        Ordering::Less => remove(&mut node.left, key),
+        Ordering::Greater => remove(&mut node.right, key),
+        Ordering::Equal => {
+            let node = link.take().unwrap();
+            *link = merge(node.left, node.right);
+            return true;
+        }
+    };
+    node.update();
+    found
+}
+
+/// Interval tree over the events of a calendar (a treap keyed by start
+/// time and id), answering overlap queries in O(log n + k) instead of
+/// scanning every event
+#[derive(Default)]
+pub(crate) struct IntervalIndex {
+    root: Link,
+    len: usize,
+}
+
+impl IntervalIndex {
+    /// add the interval of an event, the (start, id) pair must not already
+    /// be in the index
+    pub fn insert(&mut self, start: NaiveDateTime, end: NaiveDateTime, id: Uuid) {
+        let (left, right) = split(self.root.take(), (start, id));
+        self.root = merge(merge(left, Some(Node::new(start, end, id))), right);
+        self.len += 1;
+    }
+
+    /// remove the interval of an event, returning false if it was not found
+    pub fn remove(&mut self, start: NaiveDateTime, id: Uuid) -> bool {
+        let found = remove(&mut self.root, (start, id));
+        if found {
+            self.len -= 1;
+        }
+        found
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// ids of all intervals sharing at least one instant with the closed
+    /// range from start to end, touching endpoints included
+    pub fn overlapping(&self, start: NaiveDateTime, end: NaiveDateTime) -> Vec<Uuid> {
+        let mut ids = Vec::new();
+        self.for_each_overlapping(start, end, |id| {
+            ids.push(id);
+            true
+        });
+        ids
+    }
+
+    /// call `f` with the id of each interval that [`IntervalIndex::overlapping`]
+    /// would return, in no particular order, until `f` returns false
+    pub fn for_each_overlapping<F: FnMut(Uuid) -> bool>(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        mut f: F,
+    ) {
+        let mut stack: Vec<&Node> = self.root.iter().map(Box::as_ref).collect();
+
+        while let Some(node) = stack.pop() {
+            // nothing below ends late enough to reach the range
+            if node.max_end < start {
+                continue;
+            }
+            if let Some(left) = &node.left {
+                stack.push(left);
+            }
+            // everything to the right starts after this node
+            if node.start > end {
+                continue;
+            }
+            if node.end >= start && !f(node.id) {
+                return;
+            }
+            if let Some(right) = &node.right {
+                stack.push(right);
+            }
+        }
+    }
+}
@@ -1,10 +1,28 @@
 use thiserror::Error;
 
+mod backend;
 mod cal;
+mod clock;
+mod color;
+mod csv;
 mod event;
+mod ics;
+mod interval;
+mod query;
+mod recur;
+mod store;
 
-pub use cal::EventCalendar;
-pub use event::Event;
+pub use backend::{EventStore, MemoryStore};
+pub use cal::{
+    CalendarConfig, CalendarDiff, EventCalendar, EventGuard, EventInTz, ImportPolicy,
+    TimelinePoint, WorkingHours,
+};
+pub use clock::{with_clock, Clock, FixedClock, SystemClock};
+pub use color::{parse_color, Color};
+pub use event::{Event, EventPatch, EventSummary};
+pub use query::Query;
+pub use recur::{Recurrence, RecurrenceRule, MAX_OCCURRENCES};
+pub use store::CalendarStore;
 use uuid::Uuid;
 
 pub trait IntoUuid {
@@ -40,6 +58,139 @@ pub enum EventError {
     /// Error for invalid end time for an event
     #[error("end time/date cannot be before start time/date")]
     InvalidEndTime,
+
+    /// Error for a duration that must be positive but is not
+    #[error("duration must be positive")]
+    InvalidDuration,
+
+    /// Error for a partial update that could not be understood
+    #[error("invalid patch: {0}")]
+    InvalidPatch(String),
+
+    /// Error for an id that does not belong to any event in the calendar
+    #[error("no event with id {0}")]
+    NotFound(Uuid),
+
+    /// Error for joining events that neither overlap nor touch
+    #[error("events do not overlap or touch")]
+    Disjoint,
+
+    /// Error for a color that is not `#rgb`, `#rrggbb` or a known name
+    #[error("invalid color: {0}")]
+    InvalidColor(String),
+
+    /// Error for a custom property key that cannot be written as an
+    /// iCalendar X- property name
+    #[error("invalid property key {0:?}, only ASCII letters, digits and '-' are allowed")]
+    InvalidPropertyKey(String),
+
+    /// Error for a monthly recurrence on the nth weekday with n outside
+    /// `1..=5` or `-5..=-1`
+    #[error("invalid weekday position {0}, expected 1 to 5 or -5 to -1")]
+    InvalidSetPos(i8),
+}
+
+/// Errors that can occur while reading iCalendar (.ics) data
+#[derive(Error, Debug)]
+pub enum IcsError {
+    /// Error for a block that is not wrapped in BEGIN:VEVENT/END:VEVENT
+    #[error("expected a block wrapped in BEGIN:VEVENT and END:VEVENT")]
+    NotAnEvent,
+
+    /// Error for a content line that is not of the form NAME[;PARAMS]:VALUE
+    #[error("malformed content line: {0}")]
+    MalformedLine(String),
+
+    /// Error for a required property that was not found
+    #[error("missing required property {0}")]
+    MissingProperty(&'static str),
+
+    /// Error for a DATE or DATE-TIME value that could not be parsed
+    #[error("invalid date/time value: {0}")]
+    InvalidDateTime(String),
+
+    /// Error for a DURATION value that could not be parsed
+    #[error("invalid duration value: {0}")]
+    InvalidDuration(String),
+
+    /// Error for an RRULE using parts that calib cannot represent, e.g.
+    /// yearly rules or BYMONTHDAY
+    #[error("unsupported recurrence rule: {0}")]
+    UnsupportedRecurrence(String),
+
+    /// Error for start and end times that do not form a valid event
+    #[error(transparent)]
+    InvalidEvent(#[from] EventError),
+}
+
+/// Errors that can occur while expanding recurring events
+#[derive(Error, Debug)]
+pub enum RecurError {
+    /// Error for a window holding more than [`MAX_OCCURRENCES`] occurrences
+    #[error("more than {MAX_OCCURRENCES} occurrences, narrow the window")]
+    TooMany,
+}
+
+/// Errors that can occur when working with a [`CalendarStore`]
+#[derive(Error, Debug)]
+pub enum StoreError {
+    /// Error for an id that does not belong to any calendar in the store
+    #[error("no calendar with id {0}")]
+    CalendarNotFound(Uuid),
+
+    /// Error for an id that does not belong to any event in the calendar
+    #[error("no event with id {0}")]
+    EventNotFound(Uuid),
+}
+
+/// Errors that can occur while loading a saved calendar
+#[derive(Error, Debug)]
+pub enum LoadError {
+    /// Error for data that could not be read or is not a valid saved calendar
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// Error for a saved event whose start time is not before its end time
+    #[error("event {0} has a start time after its end time")]
+    InvalidEvent(Uuid),
+}
+
+/// Errors that can occur while importing events with
+/// [`EventCalendar::import_auto`]
+#[derive(Error, Debug)]
+pub enum ImportError {
+    /// Error for input that could not be read or is not UTF-8
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Error for input that is not JSON, CSV or iCalendar
+    #[error("input is not JSON, CSV or iCalendar")]
+    UnknownFormat,
+
+    /// Error for invalid JSON input
+    #[error(transparent)]
+    Json(#[from] LoadError),
+
+    /// Error for invalid iCalendar input
+    #[error(transparent)]
+    Ics(#[from] IcsError),
+
+    /// Error for a CSV row that could not be read, lines count from 1
+    #[error("invalid CSV on line {line}: {reason}")]
+    Csv { line: usize, reason: String },
+}
+
+/// Errors that can occur while decoding a calendar from bytes
+#[cfg(feature = "binary")]
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    /// Error for bytes that are not a valid encoded calendar
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+
+    /// Error for an encoded event whose start time is not before its end time
+    #[error("event {0} has a start time after its end time")]
+    InvalidEvent(Uuid),
 }
 
 /// returns a NaiveTime of 11:59:59
@@ -69,6 +220,164 @@ pub fn day_start() -> chrono::NaiveTime {
     chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap()
 }
 
+/// returns the first instant of `date` in the time zone `tz`, this is
+/// midnight unless a DST transition skips it, in which case it is the first
+/// local time that exists on that date
+///
+/// # Examples
+/// ```
+/// use calib::day_start_in_tz;
+/// use chrono::{NaiveDate, TimeZone};
+/// use chrono_tz::America::New_York;
+///
+/// let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+/// let start = day_start_in_tz(date, New_York);
+/// assert_eq!(start, New_York.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap())
+/// ```
+pub fn day_start_in_tz<Tz: chrono::TimeZone>(
+    date: chrono::NaiveDate,
+    tz: Tz,
+) -> chrono::DateTime<Tz> {
+    let mut local = date.and_time(day_start());
+
+    // DST gaps are at most a few hours long, so this always finds a time
+    loop {
+        if let Some(dt) = tz.from_local_datetime(&local).earliest() {
+            return dt;
+        }
+        local += chrono::Duration::minutes(15);
+    }
+}
+
+/// returns the last instant (to the second) of `date` in the time zone `tz`,
+/// one second before the start of the next day. On dates with a DST
+/// transition the day is 23 or 25 hours long
+///
+/// # Examples
+/// ```
+/// use calib::{day_end_in_tz, day_start_in_tz};
+/// use chrono::NaiveDate;
+/// use chrono_tz::America::New_York;
+///
+/// // clocks go back an hour on this date
+/// let date = NaiveDate::from_ymd_opt(2023, 11, 5).unwrap();
+/// let length = day_end_in_tz(date, New_York) - day_start_in_tz(date, New_York);
+/// assert_eq!(length.num_seconds(), 25 * 3600 - 1)
+/// ```
+pub fn day_end_in_tz<Tz: chrono::TimeZone>(
+    date: chrono::NaiveDate,
+    tz: Tz,
+) -> chrono::DateTime<Tz> {
+    let next = date.succ_opt().unwrap_or(date);
+    day_start_in_tz(next, tz) - chrono::Duration::seconds(1)
+}
+
+/// splits the range from start to end into one segment per calendar day,
+/// each clamped to that day's [`day_start`] and [`day_end`]. Segments of
+/// zero length (e.g. a range ending exactly at midnight) are dropped
+///
+/// # Examples
+/// ```
+/// use calib::split_range_by_day;
+/// use chrono::NaiveDate;
+///
+/// let day = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+/// let start = day.and_hms_opt(22, 0, 0).unwrap();
+/// let end = day.succ_opt().unwrap().and_hms_opt(2, 0, 0).unwrap();
+///
+/// let segments = split_range_by_day(start, end);
+/// assert_eq!(segments.len(), 2);
+/// assert_eq!(segments[0], (day, start, day.and_hms_opt(23, 59, 59).unwrap()));
+/// assert_eq!(segments[1].2, end);
+/// ```
+pub fn split_range_by_day(
+    start: chrono::NaiveDateTime,
+    end: chrono::NaiveDateTime,
+) -> Vec<(
+    chrono::NaiveDate,
+    chrono::NaiveDateTime,
+    chrono::NaiveDateTime,
+)> {
+    if start > end {
+        return Vec::new();
+    }
+
+    if start == end {
+        return vec![(start.date(), start, end)];
+    }
+
+    start
+        .date()
+        .iter_days()
+        .take_while(|date| *date <= end.date())
+        .map(|date| {
+            let seg_start = start.max(date.and_time(day_start()));
+            let seg_end = end.min(date.and_time(day_end()));
+            (date, seg_start, seg_end)
+        })
+        .filter(|(_, seg_start, seg_end)| seg_start < seg_end)
+        .collect()
+}
+
+/// parse a time typed by a person, accepting `9am`, `9:15 pm`, `09:00`,
+/// `14:30`, `14:30:15`, `noon` and `midnight` in any case. Returns None for
+/// anything else, including a bare hour without am/pm. A leap second such
+/// as `23:59:60` is kept as chrono's leap second, 23:59:59 plus one second
+/// of nanoseconds
+///
+/// # Examples
+/// ```
+/// use calib::parse_time_loose;
+/// use chrono::NaiveTime;
+///
+/// assert_eq!(parse_time_loose("9:30 PM"), NaiveTime::from_hms_opt(21, 30, 0));
+/// assert_eq!(parse_time_loose("25:00"), None);
+/// ```
+pub fn parse_time_loose(s: &str) -> Option<chrono::NaiveTime> {
+    let s = s.trim().to_ascii_lowercase();
+
+    match s.as_str() {
+        "noon" => return chrono::NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return Some(day_start()),
+        _ => {}
+    }
+
+    let (clock, pm) = match (s.strip_suffix("am"), s.strip_suffix("pm")) {
+        (Some(clock), _) => (clock.trim_end(), Some(false)),
+        (_, Some(clock)) => (clock.trim_end(), Some(true)),
+        _ => (s.as_str(), None),
+    };
+
+    let digits = |part: &str, len: std::ops::RangeInclusive<usize>| {
+        (len.contains(&part.len()) && part.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| part.parse::<u32>().ok())
+            .flatten()
+    };
+    let mut parts = clock.split(':');
+    let (hour, min, sec) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(hour), Some(min), None, None) => (digits(hour, 1..=2)?, digits(min, 2..=2)?, 0),
+        (Some(hour), Some(min), Some(sec), None) => (
+            digits(hour, 1..=2)?,
+            digits(min, 2..=2)?,
+            digits(sec, 2..=2)?,
+        ),
+        // a bare hour is only clear with am/pm
+        (Some(hour), None, _, _) if pm.is_some() => (digits(hour, 1..=2)?, 0, 0),
+        _ => return None,
+    };
+
+    let hour = match pm {
+        None => hour,
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+    };
+
+    match sec {
+        60 => chrono::NaiveTime::from_hms_nano_opt(hour, min, 59, 1_000_000_000),
+        _ => chrono::NaiveTime::from_hms_opt(hour, min, sec),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
@@ -158,6 +467,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_invalid_event_time_change() {
         // basic date declaration
         let naive_date = first_day_2023_nd();
@@ -179,6 +489,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn invalid_events_test() {
         // basic date declaration
         let naive_date = first_day_2023_nd();
@@ -269,6 +580,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
     fn test_event_range() {
         let nd1 = first_day_2023_nd();
         let nd2 = nd1.with_day(2).unwrap();
@@ -314,6 +626,7 @@ mod test {
         let nd = first_day_2023_nd();
         let e = Event::new("A".into(), &nd);
         let id = e.id().to_string();
+        let modified = serde_json::to_string(&e.modified()).unwrap();
 
         let first_time = first_day_2023_ndt().format("%Y-%m-%dT%H:%M:%S").to_string();
         let last_time = NaiveDateTime::new(nd, last_time_nt())
@@ -322,7 +635,3716 @@ mod test {
 
         assert_eq!(
             e.serialize(),
-            format!("{{\"start\":\"{first_time}\",\"end\":\"{last_time}\",\"name\":\"A\",\"id\":\"{id}\"}}",)
+            format!("{{\"start\":\"{first_time}\",\"end\":\"{last_time}\",\"name\":\"A\",\"id\":\"{id}\",\"modified\":{modified}}}",)
+        )
+    }
+
+    #[test]
+    fn test_event_from_ics_vevent() {
+        let block = "BEGIN:VEVENT\r\n\
+                     UID:67e55044-10b1-426f-9247-bb680e5fe0c8\r\n\
+                     DTSTART:20230101T090000\r\n\
+                     DTEND:20230101T100000\r\n\
+                     SUMMARY:Standup\r\n\
+                     END:VEVENT\r\n";
+
+        let e = Event::from_ics_vevent(block).unwrap();
+        let nd = first_day_2023_nd();
+
+        assert_eq!(e.name(), "Standup");
+        assert_eq!(e.start(), nd.and_hms_opt(9, 0, 0).unwrap());
+        assert_eq!(e.end(), nd.and_hms_opt(10, 0, 0).unwrap());
+        assert_eq!(e.id().to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+
+        // all day events use DATE values with an exclusive end
+        let block = "BEGIN:VEVENT\n\
+                     DTSTART;VALUE=DATE:20230101\n\
+                     DTEND;VALUE=DATE:20230102\n\
+                     SUMMARY:Holiday\n\
+                     END:VEVENT";
+
+        let e = Event::from_ics_vevent(block).unwrap();
+        assert_eq!(e.start(), first_day_2023_ndt());
+        assert_eq!(e.end(), NaiveDateTime::new(nd, last_time_nt()));
+
+        // missing DTEND gives an open-ended event, missing DTSTART and
+        // missing BEGIN:VEVENT are errors
+        let block = "BEGIN:VEVENT\nDTSTART:20230101T090000\nEND:VEVENT";
+        assert!(Event::from_ics_vevent(block).unwrap().is_open_ended());
+        let block = "BEGIN:VEVENT\nDTEND:20230101T090000\nEND:VEVENT";
+        assert!(Event::from_ics_vevent(block).is_err());
+        assert!(Event::from_ics_vevent("DTSTART:20230101T090000").is_err());
+    }
+
+    #[test]
+    fn test_event_from_ics_vevent_escaped_summary() {
+        let block = "BEGIN:VEVENT\n\
+                     DTSTART:20230101T090000\n\
+                     DTEND:20230101T100000\n\
+                     SUMMARY:Lunch\\, drinks\\; dessert\n\
+                     END:VEVENT";
+
+        let e = Event::from_ics_vevent(block).unwrap();
+        assert_eq!(e.name(), "Lunch, drinks; dessert");
+    }
+
+    #[test]
+    fn test_calendar_diff() {
+        let nd = first_day_2023_nd();
+
+        let kept = Event::new("Kept".into(), &nd);
+        let removed = Event::new("Removed".into(), &nd);
+        let moved = Event::new("Moved".into(), &nd);
+        let added = Event::new("Added".into(), &nd);
+
+        let (kept_id, removed_id, moved_id, added_id) =
+            (*kept.id(), *removed.id(), *moved.id(), *added.id());
+
+        let mut old = EventCalendar::default();
+        old.add_event(kept.clone());
+        old.add_event(removed);
+        old.add_event(moved.clone());
+
+        let mut new = EventCalendar::default();
+        new.add_event(kept);
+        new.add_event(
+            moved
+                .set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+                .unwrap(),
+        );
+        new.add_event(added);
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![added_id]);
+        assert_eq!(diff.removed, vec![removed_id]);
+        assert_eq!(diff.changed, vec![moved_id]);
+        assert!(!diff.changed.contains(&kept_id));
+
+        // a calendar has no differences with itself
+        assert_eq!(old.diff(&old), CalendarDiff::default());
+    }
+
+    #[test]
+    fn test_event_modified_advances() {
+        let created = first_day_2023_ndt();
+        let renamed = created + chrono::Duration::minutes(1);
+        let moved = renamed + chrono::Duration::minutes(1);
+
+        let mut e = with_clock(FixedClock(created), || {
+            Event::new("A".into(), &first_day_2023_nd())
+        });
+        assert_eq!(e.modified(), created);
+
+        with_clock(FixedClock(renamed), || e.set_name("B".into()));
+        assert_eq!(e.modified(), renamed);
+
+        let e = with_clock(FixedClock(moved), || {
+            e.set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+        })
+        .unwrap();
+        assert_eq!(e.modified(), moved);
+    }
+
+    #[test]
+    fn test_ongoing_events_fixed_clock() {
+        let nd = first_day_2023_nd();
+
+        let all_day = Event::new("All day".into(), &nd);
+        let morning = Event::new("Morning".into(), &nd)
+            .set_end_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap())
+            .unwrap();
+        let tomorrow = Event::new("Tomorrow".into(), &nd.with_day(2).unwrap());
+
+        let (all_day_id, morning_id) = (*all_day.id(), *morning.id());
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(all_day);
+        cal.add_event(morning);
+        cal.add_event(tomorrow);
+
+        let clock = FixedClock(nd.and_hms_opt(9, 0, 0).unwrap());
+        let mut ids: Vec<_> = cal.ongoing_events(&clock).map(|e| *e.id()).collect();
+        ids.sort();
+        let mut expected = vec![all_day_id, morning_id];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        let clock = FixedClock(nd.and_hms_opt(13, 0, 0).unwrap());
+        let ids: Vec<_> = cal.ongoing_events(&clock).map(|e| *e.id()).collect();
+        assert_eq!(ids, vec![all_day_id]);
+    }
+
+    #[test]
+    fn test_event_to_ics_vevent_escaping() {
+        let mut e = Event::new("A".into(), &first_day_2023_nd())
+            .set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+            .unwrap();
+        e.set_name("Lunch, then\nnap".into());
+
+        let block = e.to_ics_vevent();
+        assert!(block.starts_with("BEGIN:VEVENT\r\n"));
+        assert!(block.ends_with("END:VEVENT\r\n"));
+        assert!(block.contains("SUMMARY:Lunch\\, then\\nnap\r\n"));
+        assert!(block.contains("DTSTART:20230101T090000\r\n"));
+        assert!(block.contains(&format!("UID:{}\r\n", e.id())));
+
+        // the exported block can be read back in
+        let parsed = Event::from_ics_vevent(&block).unwrap();
+        assert_eq!(parsed.name(), e.name());
+        assert_eq!(parsed.start(), e.start());
+        assert_eq!(parsed.end(), e.end());
+        assert_eq!(parsed.id(), e.id());
+
+        // all day events are written as dates
+        let e = Event::new("Holiday".into(), &first_day_2023_nd());
+        let block = e.to_ics_vevent();
+        assert!(block.contains("DTSTART;VALUE=DATE:20230101\r\n"));
+        assert!(block.contains("DTEND;VALUE=DATE:20230102\r\n"));
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(e);
+        let ics = cal.to_ics();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains(&block));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn test_get_or_create() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+
+        let first_id = *cal
+            .get_or_create(|e| e.name() == "Sync", || Event::new("Sync".into(), &nd))
+            .id();
+
+        let second_id = *cal
+            .get_or_create(|e| e.name() == "Sync", || Event::new("Sync".into(), &nd))
+            .id();
+
+        assert_eq!(first_id, second_id);
+        assert_eq!(cal.first_event().map(|e| *e.id()), Some(first_id));
+        assert_eq!(cal.diff(&EventCalendar::default()).removed.len(), 1);
+    }
+
+    #[test]
+    fn test_event_round_times() {
+        let nd = first_day_2023_nd();
+        let e = Event::new("A".into(), &nd)
+            .set_end_time(NaiveTime::from_hms_opt(10, 52, 0).unwrap())
+            .unwrap()
+            .set_start_time(NaiveTime::from_hms_opt(9, 7, 0).unwrap())
+            .unwrap();
+
+        let e = e.round_times(chrono::Duration::minutes(15)).unwrap();
+        assert_eq!(e.start(), nd.and_hms_opt(9, 0, 0).unwrap());
+        assert_eq!(e.end(), nd.and_hms_opt(11, 0, 0).unwrap());
+
+        // times already on a boundary are left alone
+        let e = e.round_times(chrono::Duration::minutes(15)).unwrap();
+        assert_eq!(e.start(), nd.and_hms_opt(9, 0, 0).unwrap());
+        assert_eq!(e.end(), nd.and_hms_opt(11, 0, 0).unwrap());
+
+        assert!(e.round_times(chrono::Duration::zero()).is_err());
+    }
+
+    #[test]
+    fn test_events_in_range_where() {
+        let nd = first_day_2023_nd();
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("Meeting with Bob".into(), &nd));
+        cal.add_event(Event::new("Lunch".into(), &nd));
+        cal.add_event(Event::new(
+            "Meeting with Alice".into(),
+            &nd.with_day(2).unwrap(),
+        ));
+        cal.add_event(Event::new(
+            "Meeting next week".into(),
+            &nd.with_day(9).unwrap(),
+        ));
+
+        let range_end = NaiveDateTime::new(nd.with_day(3).unwrap(), day_end());
+        let mut names: Vec<_> = cal
+            .events_in_range_where(first_day_2023_ndt(), range_end, |e| {
+                e.name().starts_with("Meeting")
+            })
+            .map(|e| e.name())
+            .collect();
+        names.sort();
+
+        assert_eq!(names, vec!["Meeting with Alice", "Meeting with Bob"]);
+    }
+
+    #[test]
+    fn test_event_time_until() {
+        let nd = first_day_2023_nd();
+        let e = Event::new("A".into(), &nd)
+            .set_start_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap())
+            .unwrap();
+
+        // future
+        let now = nd.and_hms_opt(10, 0, 0).unwrap();
+        assert_eq!(e.time_until(now), chrono::Duration::hours(2));
+        assert_eq!(e.time_until_human(now), "in 2 hours");
+
+        // ongoing
+        let now = nd.and_hms_opt(12, 1, 30).unwrap();
+        assert_eq!(e.time_until(now), chrono::Duration::seconds(-90));
+        assert_eq!(e.time_until_human(now), "1 minute ago");
+
+        // past
+        let now = NaiveDateTime::new(nd.with_day(4).unwrap(), first_time_nt());
+        assert!(e.time_until(now) < chrono::Duration::zero());
+        assert_eq!(e.time_until_human(now), "2 days ago");
+
+        assert_eq!(e.time_until_human(e.start()), "now");
+    }
+
+    #[test]
+    fn test_calendar_import_policies() {
+        let nd = first_day_2023_nd();
+        let original = with_clock(FixedClock(first_day_2023_ndt()), || {
+            Event::new("Original".into(), &nd)
+        });
+        let id = *original.id();
+
+        // same id but modified later
+        let mut newer = original.clone();
+        with_clock(
+            FixedClock(first_day_2023_ndt() + chrono::Duration::hours(1)),
+            || newer.set_name("Newer".into()),
+        );
+
+        let other_with = |evt: &Event| {
+            let mut other = EventCalendar::default();
+            other.add_event(evt.clone());
+            other.add_event(Event::new("Extra".into(), &nd));
+            other
+        };
+
+        let run = |existing: &Event, incoming: &Event, policy| {
+            let mut cal = EventCalendar::default();
+            cal.add_event(existing.clone());
+            cal.import(other_with(incoming), policy);
+            assert_eq!(
+                cal.events_in_range(first_day_2023_ndt(), NaiveDateTime::new(nd, day_end()))
+                    .count(),
+                2
+            );
+            cal.get(id).unwrap().name().to_string()
+        };
+
+        assert_eq!(
+            run(&original, &newer, ImportPolicy::SkipExisting),
+            "Original"
+        );
+        assert_eq!(
+            run(&original, &newer, ImportPolicy::OverwriteExisting),
+            "Newer"
+        );
+        assert_eq!(
+            run(&original, &newer, ImportPolicy::KeepNewerByModified),
+            "Newer"
+        );
+        assert_eq!(
+            run(&newer, &original, ImportPolicy::KeepNewerByModified),
+            "Newer"
+        );
+    }
+
+    #[test]
+    fn test_event_apply_patch() {
+        let nd = first_day_2023_nd();
+        let e = Event::new("A".into(), &nd);
+        let id = *e.id();
+
+        let new_start = nd.and_hms_opt(9, 0, 0).unwrap();
+        let e = e
+            .apply_patch(EventPatch {
+                name: Some("B".into()),
+                start: Some(new_start),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(e.name(), "B");
+        assert_eq!(e.start(), new_start);
+        assert_eq!(e.end(), NaiveDateTime::new(nd, last_time_nt()));
+        assert_eq!(*e.id(), id);
+
+        // moving both times to the next day only works because the patch is
+        // validated as a whole
+        let next_day = nd.with_day(2).unwrap();
+        let e = e
+            .apply_patch(EventPatch {
+                start: Some(next_day.and_hms_opt(9, 0, 0).unwrap()),
+                end: Some(next_day.and_hms_opt(10, 0, 0).unwrap()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(e.start().date(), next_day);
+
+        // the combined result is invalid
+        let invalid = e.apply_patch(EventPatch {
+            name: Some("C".into()),
+            start: Some(next_day.and_hms_opt(11, 0, 0).unwrap()),
+            end: Some(next_day.and_hms_opt(10, 0, 0).unwrap()),
+        });
+        assert!(matches!(invalid, Err(EventError::InvalidStartTime)));
+    }
+
+    #[test]
+    fn test_calendar_patch_event() {
+        let nd = first_day_2023_nd();
+        let e1 = Event::new("A".into(), &nd);
+        let e2 = Event::new("B".into(), &nd.with_day(2).unwrap());
+        let (e1_id, e2_id) = (*e1.id(), *e2.id());
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(e1);
+        cal.add_event(e2);
+        assert_eq!(cal.first_event().map(|e| *e.id()), Some(e1_id));
+
+        // moving A after B re-sorts the calendar
+        let day3 = nd.with_day(3).unwrap();
+        cal.patch_event(
+            e1_id,
+            EventPatch {
+                start: Some(day3.and_hms_opt(9, 0, 0).unwrap()),
+                end: Some(day3.and_hms_opt(10, 0, 0).unwrap()),
+                ..Default::default()
+            },
         )
+        .unwrap();
+        assert_eq!(cal.first_event().map(|e| *e.id()), Some(e2_id));
+        assert_eq!(cal.get(e1_id).unwrap().start().date(), day3);
+
+        // an invalid patch leaves the calendar untouched
+        let before = cal.diff(&EventCalendar::default());
+        let status = cal.patch_event(
+            e2_id,
+            EventPatch {
+                name: Some("C".into()),
+                end: Some(nd.and_hms_opt(1, 0, 0).unwrap()),
+                ..Default::default()
+            },
+        );
+        assert!(status.is_err());
+        assert_eq!(cal.get(e2_id).unwrap().name(), "B");
+        assert_eq!(cal.diff(&EventCalendar::default()), before);
+        assert_eq!(cal.first_event().map(|e| *e.id()), Some(e2_id));
+
+        let missing = cal.patch_event(Uuid::new_v4(), EventPatch::default());
+        assert!(matches!(missing, Err(EventError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_event_ics_offset_round_trip() {
+        let block = "BEGIN:VEVENT\n\
+                     DTSTART:20230101T120000-0500\n\
+                     DTEND:20230101T180000Z\n\
+                     SUMMARY:Offset\n\
+                     END:VEVENT";
+
+        let e = Event::from_ics_vevent(block).unwrap();
+        let nd = first_day_2023_nd();
+
+        // the end time is converted into the start time's offset
+        assert_eq!(e.offset(), chrono::FixedOffset::west_opt(5 * 3600));
+        assert_eq!(e.start(), nd.and_hms_opt(12, 0, 0).unwrap());
+        assert_eq!(e.end(), nd.and_hms_opt(13, 0, 0).unwrap());
+
+        let exported = e.to_ics_vevent();
+        assert!(exported.contains("DTSTART:20230101T120000-0500\r\n"));
+        assert!(exported.contains("DTEND:20230101T130000-0500\r\n"));
+
+        // re-importing gives back the same times without drift
+        let reparsed = Event::from_ics_vevent(&exported).unwrap();
+        assert_eq!(reparsed.start(), e.start());
+        assert_eq!(reparsed.end(), e.end());
+        assert_eq!(reparsed.offset(), e.offset());
+
+        // UTC times are written back with a Z and floating times have no offset
+        let utc = "BEGIN:VEVENT\nDTSTART:20230101T120000Z\nDTEND:20230101T130000Z\nEND:VEVENT";
+        let e = Event::from_ics_vevent(utc).unwrap();
+        assert!(e.to_ics_vevent().contains("DTSTART:20230101T120000Z\r\n"));
+        assert_eq!(Event::new("A".into(), &nd).offset(), None);
+    }
+
+    #[test]
+    fn test_event_properties() {
+        let mut e = Event::new("A".into(), &first_day_2023_nd());
+        assert_eq!(e.get_property("room"), None);
+
+        assert_eq!(e.set_property("room".into(), "B12".into()).unwrap(), None);
+        e.set_property("url".into(), "https://example.com/meet".into())
+            .unwrap();
+        assert_eq!(e.get_property("room"), Some("B12"));
+
+        // overwriting returns the previous value
+        assert_eq!(
+            e.set_property("room".into(), "C3".into()).unwrap(),
+            Some("B12".to_string())
+        );
+        assert_eq!(e.get_property("room"), Some("C3"));
+
+        let json = e.serialize();
+        assert!(
+            json.contains("\"properties\":{\"room\":\"C3\",\"url\":\"https://example.com/meet\"}")
+        );
+
+        // properties survive an .ics round trip as X- properties
+        let block = e.to_ics_vevent();
+        assert!(block.contains("X-room:C3\r\n"));
+        let parsed = Event::from_ics_vevent(&block).unwrap();
+        assert_eq!(parsed.properties(), e.properties());
+
+        assert_eq!(e.remove_property("room"), Some("C3".to_string()));
+        assert_eq!(e.remove_property("room"), None);
+    }
+
+    #[test]
+    fn test_events_grouped_by_tag() {
+        let nd = first_day_2023_nd();
+
+        let mut standup = Event::new("Standup".into(), &nd);
+        standup.add_tag("work".into());
+        let mut review = Event::new("Review".into(), &nd.with_day(2).unwrap());
+        review.add_tag("work".into());
+        review.add_tag("code".into());
+        let lunch = Event::new("Lunch".into(), &nd.with_day(3).unwrap());
+
+        assert!(!standup.add_tag("work".into()));
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(lunch);
+        cal.add_event(review);
+        cal.add_event(standup);
+
+        let groups = cal.events_grouped_by_tag();
+        let names = |tag: &str| -> Vec<&str> { groups[tag].iter().map(|e| e.name()).collect() };
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(names("work"), vec!["Standup", "Review"]);
+        assert_eq!(names("code"), vec!["Review"]);
+        assert_eq!(names("untagged"), vec!["Lunch"]);
+    }
+
+    #[test]
+    fn test_event_tags_ics_round_trip() {
+        let mut e = Event::new("A".into(), &first_day_2023_nd());
+        e.add_tag("work".into());
+        e.add_tag("a, b".into());
+
+        let block = e.to_ics_vevent();
+        assert!(block.contains("CATEGORIES:a\\, b,work\r\n"));
+        assert_eq!(Event::from_ics_vevent(&block).unwrap().tags(), e.tags());
+    }
+
+    #[test]
+    fn test_split_range_by_day() {
+        let nd = first_day_2023_nd();
+
+        // same day
+        let start = nd.and_hms_opt(9, 0, 0).unwrap();
+        let end = nd.and_hms_opt(17, 0, 0).unwrap();
+        assert_eq!(split_range_by_day(start, end), vec![(nd, start, end)]);
+
+        // two days
+        let end = nd.with_day(2).unwrap().and_hms_opt(3, 0, 0).unwrap();
+        assert_eq!(
+            split_range_by_day(start, end),
+            vec![
+                (nd, start, NaiveDateTime::new(nd, day_end())),
+                (end.date(), NaiveDateTime::new(end.date(), day_start()), end),
+            ]
+        );
+
+        // a week, ending exactly at midnight of the 8th
+        let end = NaiveDateTime::new(nd.with_day(8).unwrap(), day_start());
+        let segments = split_range_by_day(start, end);
+        assert_eq!(segments.len(), 7);
+        assert_eq!(segments[0].1, start);
+        for (i, (date, seg_start, seg_end)) in segments.iter().enumerate().skip(1) {
+            assert_eq!(date.day(), i as u32 + 1);
+            assert_eq!(seg_start.time(), day_start());
+            assert_eq!(seg_end.time(), day_end());
+        }
+
+        // the event wrapper uses the same logic
+        let e = Event::new("A".into(), &nd);
+        assert_eq!(e.split_by_day(), vec![(nd, e.start(), e.end())]);
+
+        assert!(split_range_by_day(end, start).is_empty());
+    }
+
+    #[test]
+    fn test_calendar_save_load_round_trip() {
+        let nd = first_day_2023_nd();
+
+        let mut tagged = Event::new("Tagged".into(), &nd.with_day(2).unwrap());
+        tagged.add_tag("work".into());
+        tagged.set_property("room".into(), "B12".into()).unwrap();
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("A".into(), &nd));
+        cal.add_event(tagged);
+
+        let mut buf: Vec<u8> = Vec::new();
+        cal.save(&mut buf).unwrap();
+
+        let loaded = EventCalendar::load(buf.as_slice()).unwrap();
+        assert_eq!(cal.diff(&loaded), CalendarDiff::default());
+        assert_eq!(loaded.diff(&cal), CalendarDiff::default());
+
+        // garbage and invalid events are rejected
+        assert!(matches!(
+            EventCalendar::load("not json".as_bytes()),
+            Err(LoadError::Json(_))
+        ));
+
+        let id = Uuid::new_v4();
+        let invalid = format!(
+            "[{{\"start\":\"2023-01-02T00:00:00\",\"end\":\"2023-01-01T00:00:00\",\"name\":\"A\",\"id\":\"{id}\"}}]"
+        );
+        assert!(matches!(
+            EventCalendar::load(invalid.as_bytes()),
+            Err(LoadError::InvalidEvent(bad)) if bad == id
+        ));
+    }
+
+    #[test]
+    fn test_event_normalize_all_day() {
+        let nd = first_day_2023_nd();
+        let e = Event::new("A".into(), &nd);
+        assert!(e.is_all_day());
+
+        // ends at 23:59:00 so it is snapped to the end of the day
+        let e = e
+            .set_end_time(NaiveTime::from_hms_opt(23, 59, 0).unwrap())
+            .unwrap();
+        assert!(!e.is_all_day());
+        let e = e.normalize_all_day();
+        assert!(e.is_all_day());
+        assert_eq!(e.end(), NaiveDateTime::new(nd, last_time_nt()));
+
+        // ends exactly at the next midnight
+        let e = e
+            .set_end(NaiveDateTime::new(nd.with_day(2).unwrap(), first_time_nt()))
+            .unwrap()
+            .normalize_all_day();
+        assert!(e.is_all_day());
+        assert_eq!(e.end(), NaiveDateTime::new(nd, last_time_nt()));
+
+        // a midday start is left alone
+        let midday = nd.and_hms_opt(12, 0, 0).unwrap();
+        let e = e.set_start(midday).unwrap().normalize_all_day();
+        assert!(!e.is_all_day());
+        assert_eq!(e.start(), midday);
+
+        // as is an event ending well before the end of the day
+        let e = Event::new("B".into(), &nd)
+            .set_end_time(NaiveTime::from_hms_opt(18, 0, 0).unwrap())
+            .unwrap()
+            .normalize_all_day();
+        assert!(!e.is_all_day());
+    }
+
+    #[test]
+    fn test_first_free_day() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        assert_eq!(cal.first_free_day(nd), Some(nd));
+
+        // busy on the 1st, the 2nd (via an event running into it) and the 4th
+        cal.add_event(Event::new("A".into(), &nd));
+        cal.add_event(
+            Event::new("B".into(), &nd)
+                .set_end(nd.with_day(2).unwrap().and_hms_opt(1, 0, 0).unwrap())
+                .unwrap(),
+        );
+        cal.add_event(Event::new("C".into(), &nd.with_day(4).unwrap()));
+
+        assert_eq!(cal.first_free_day(nd), nd.with_day(3));
+        assert_eq!(cal.first_free_day(nd.with_day(4).unwrap()), nd.with_day(5));
+
+        // an event ending at midnight leaves the next day free
+        let mut cal = EventCalendar::default();
+        cal.add_event(
+            Event::new("D".into(), &nd)
+                .set_end(NaiveDateTime::new(nd.with_day(2).unwrap(), day_start()))
+                .unwrap(),
+        );
+        assert_eq!(cal.first_free_day(nd), nd.with_day(2));
+
+        // nothing is free once an open-ended event has started
+        cal.add_event(Event::new_open_ended(
+            "E".into(),
+            nd.with_day(5).unwrap().and_hms_opt(9, 0, 0).unwrap(),
+        ));
+        assert_eq!(cal.first_free_day(nd), nd.with_day(2));
+        assert_eq!(cal.first_free_day(nd.with_day(5).unwrap()), None);
+        assert_eq!(cal.first_free_day(nd.with_day(9).unwrap()), None);
+    }
+
+    #[test]
+    fn test_archive_event() {
+        let nd = first_day_2023_nd();
+        let e1 = Event::new("A".into(), &nd);
+        let e2 = Event::new("B".into(), &nd);
+        let e1_id = *e1.id();
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(e1);
+        cal.add_event(e2);
+
+        let day_end = NaiveDateTime::new(nd, day_end());
+
+        assert!(cal.archive_event(e1_id));
+        assert!(!cal.archive_event(e1_id));
+        assert!(cal.get(e1_id).is_none());
+        assert_eq!(cal.iter().count(), 1);
+        assert!(cal.iter().all(|e| *e.id() != e1_id));
+        assert_eq!(
+            cal.events_in_range(first_day_2023_ndt(), day_end).count(),
+            1
+        );
+        assert_eq!(
+            cal.archived_events().map(|e| *e.id()).collect::<Vec<_>>(),
+            vec![e1_id]
+        );
+
+        assert!(cal.unarchive_event(e1_id));
+        assert!(!cal.unarchive_event(e1_id));
+        assert_eq!(cal.get(e1_id).map(|e| e.name()), Some("A"));
+        assert_eq!(
+            cal.events_in_range(first_day_2023_ndt(), day_end).count(),
+            2
+        );
+        assert_eq!(cal.archived_events().count(), 0);
+    }
+
+    #[test]
+    fn test_reschedule_conflicts() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+        let timed = |name: &str, start, end| {
+            Event::new(name.into(), &nd)
+                .set_start(start)
+                .unwrap()
+                .set_end(end)
+                .unwrap()
+        };
+
+        let a = timed("A", at(9, 0), at(10, 0));
+        let b = timed("B", at(9, 30), at(10, 0));
+        let c = timed("C", at(9, 45), at(11, 0));
+        let d = timed("D", at(14, 0), at(15, 0));
+        let (b_id, c_id) = (*b.id(), *c.id());
+
+        let mut cal = EventCalendar::default();
+        for e in [a, b, c, d] {
+            cal.add_event(e);
+        }
+
+        let moved = cal.reschedule_conflicts(chrono::Duration::minutes(5));
+        assert_eq!(moved, vec![(b_id, at(10, 5)), (c_id, at(10, 40))]);
+
+        // durations and order are preserved and nothing overlaps anymore
+        let times: Vec<_> = cal.iter().map(|e| (e.name(), e.start(), e.end())).collect();
+        assert_eq!(
+            times,
+            vec![
+                ("A", at(9, 0), at(10, 0)),
+                ("B", at(10, 5), at(10, 35)),
+                ("C", at(10, 40), at(11, 55)),
+                ("D", at(14, 0), at(15, 0)),
+            ]
+        );
+
+        assert!(cal
+            .reschedule_conflicts(chrono::Duration::minutes(5))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_pairs() {
+        let nd = first_day_2023_nd();
+        let timed = |name: &str, (sh, sm), (eh, em)| {
+            Event::new(name.into(), &nd)
+                .set_start_time(NaiveTime::from_hms_opt(sh, sm, 0).unwrap())
+                .unwrap()
+                .set_end_time(NaiveTime::from_hms_opt(eh, em, 0).unwrap())
+                .unwrap()
+        };
+
+        // disjoint, including two events that only touch
+        let mut cal = EventCalendar::default();
+        cal.add_event(timed("A", (9, 0), (10, 0)));
+        cal.add_event(timed("B", (10, 0), (11, 0)));
+        cal.add_event(timed("C", (13, 0), (14, 0)));
+        assert!(cal.overlapping_pairs().is_empty());
+
+        // two clusters: D/E/F all overlap and G/H overlap
+        let mut cal = EventCalendar::default();
+        cal.add_event(timed("D", (9, 0), (12, 0)));
+        cal.add_event(timed("E", (9, 30), (10, 0)));
+        cal.add_event(timed("F", (11, 0), (11, 30)));
+        cal.add_event(timed("G", (14, 0), (15, 0)));
+        cal.add_event(timed("H", (14, 30), (16, 0)));
+
+        let names: Vec<_> = cal
+            .overlapping_pairs()
+            .into_iter()
+            .map(|(a, b)| (a.name(), b.name()))
+            .collect();
+        assert_eq!(names, vec![("D", "E"), ("D", "F"), ("G", "H")]);
+    }
+
+    #[test]
+    fn test_event_day_position() {
+        let nd = first_day_2023_nd();
+        let next = nd.with_day(2).unwrap();
+
+        let e = Event::new("A".into(), &nd)
+            .set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+            .unwrap()
+            .set_end_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(e.day_position(nd), Some((0.375, 0.125)));
+        assert_eq!(e.day_position(next), None);
+
+        // 18:00 until 06:00 the next day is clamped to each day
+        let e = e
+            .set_end(next.and_hms_opt(6, 0, 0).unwrap())
+            .unwrap()
+            .set_start_time(NaiveTime::from_hms_opt(18, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(e.day_position(nd), Some((0.75, 0.25)));
+        assert_eq!(e.day_position(next), Some((0.0, 0.25)));
+
+        // all day events fill the whole day
+        let e = Event::new("B".into(), &nd);
+        assert_eq!(e.day_position(nd), Some((0.0, 1.0)));
+    }
+
+    #[test]
+    fn test_nth_event() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("C".into(), &nd.with_day(3).unwrap()));
+        cal.add_event(Event::new("A".into(), &nd));
+        cal.add_event(Event::new("B".into(), &nd.with_day(2).unwrap()));
+
+        assert_eq!(cal.nth_event(0).map(|e| e.name()), Some("A"));
+        assert_eq!(cal.nth_event(1).map(|e| e.name()), Some("B"));
+        assert_eq!(cal.nth_event(2).map(|e| e.name()), Some("C"));
+        assert_eq!(cal.nth_event(3), None);
+    }
+
+    #[cfg(feature = "binary")]
+    #[test]
+    fn test_calendar_binary_round_trip() {
+        let nd = first_day_2023_nd();
+
+        let mut tagged = Event::new("Tagged".into(), &nd.with_day(2).unwrap());
+        tagged.add_tag("work".into());
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("A".into(), &nd));
+        cal.add_event(tagged);
+
+        let bytes = cal.to_bytes();
+        let decoded = EventCalendar::from_bytes(&bytes).unwrap();
+        assert_eq!(cal.diff(&decoded), CalendarDiff::default());
+        assert_eq!(decoded.diff(&cal), CalendarDiff::default());
+
+        // truncated data is rejected
+        assert!(EventCalendar::from_bytes(&bytes[..bytes.len() / 2]).is_err());
+        assert!(EventCalendar::from_bytes(&[0xff; 16]).is_err());
+    }
+
+    #[test]
+    fn test_event_clamped_setters() {
+        let nd = first_day_2023_nd();
+        let e = Event::new("A".into(), &nd)
+            .set_end_time(NaiveTime::from_hms_opt(10, 0, 0).unwrap())
+            .unwrap();
+
+        // a start after the end moves the end along
+        let late = nd.and_hms_opt(11, 0, 0).unwrap();
+        assert!(e.clone().set_start(late).is_err());
+        let moved = e.clone().set_start_clamped(late);
+        assert_eq!(moved.start(), late);
+        assert_eq!(moved.end(), nd.and_hms_opt(11, 0, 1).unwrap());
+
+        // a valid start leaves the end alone
+        let early = nd.and_hms_opt(9, 0, 0).unwrap();
+        let moved = e.clone().set_start_clamped(early);
+        assert_eq!((moved.start(), moved.end()), (early, e.end()));
+
+        // an end before the start moves the start back
+        let e = e.set_start(early).unwrap();
+        let before = nd.and_hms_opt(8, 0, 0).unwrap();
+        assert!(e.clone().set_end(before).is_err());
+        let moved = e.set_end_clamped(before);
+        assert_eq!(moved.start(), nd.and_hms_opt(7, 59, 59).unwrap());
+        assert_eq!(moved.end(), before);
+    }
+
+    #[test]
+    fn test_events_in_range_vec() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in [4, 1, 3, 2, 9] {
+            cal.add_event(Event::new(day.to_string(), &nd.with_day(day).unwrap()));
+        }
+
+        let start = NaiveDateTime::new(nd.with_day(2).unwrap(), day_start());
+        let end = NaiveDateTime::new(nd.with_day(4).unwrap(), day_end());
+
+        let collected = cal.events_in_range_vec(start, end);
+        let iterated: Vec<&Event> = cal
+            .events_in_range(start, end)
+            .map(|e| e.as_ref())
+            .collect();
+        assert_eq!(collected, iterated);
+
+        let names: Vec<_> = collected.iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_recurrence_occurrences() {
+        let nd = first_day_2023_nd();
+        let window = (
+            first_day_2023_ndt(),
+            NaiveDateTime::new(nd.with_month(6).unwrap(), day_start()),
+        );
+
+        // the 31st only exists in January, March and May before June
+        let mut e = Event::new("Rent".into(), &nd.with_day(31).unwrap());
+        e.set_recurrence(Some(Recurrence::new(RecurrenceRule::Monthly {
+            interval: 1,
+            by_set_pos: None,
+        })))
+        .unwrap();
+        let months: Vec<_> = e
+            .occurrences_between(window.0, window.1)
+            .unwrap()
+            .iter()
+            .map(|occ| occ.start().month())
+            .collect();
+        assert_eq!(months, vec![1, 3, 5]);
+
+        // count and until both limit the occurrences
+        let rule = Recurrence::new(RecurrenceRule::Daily { interval: 2 });
+        e.set_recurrence(Some(rule.clone().with_count(3))).unwrap();
+        assert_eq!(e.occurrences_between(window.0, window.1).unwrap().len(), 3);
+        e.set_recurrence(Some(
+            rule.with_until(NaiveDateTime::new(nd.with_month(2).unwrap(), day_start())),
+        ))
+        .unwrap();
+        let days: Vec<_> = e
+            .occurrences_between(window.0, window.1)
+            .unwrap()
+            .iter()
+            .map(|occ| occ.start().day())
+            .collect();
+        assert_eq!(days, vec![31]);
+
+        // a plain event only yields itself
+        let plain = Event::new("A".into(), &nd);
+        assert_eq!(
+            plain.occurrences_between(window.0, window.1).unwrap(),
+            vec![plain.clone()]
+        );
+    }
+
+    #[test]
+    fn test_conflicts_expanded() {
+        let nd = first_day_2023_nd(); // a Sunday
+        let at = |day, h| nd.with_day(day).unwrap().and_hms_opt(h, 0, 0).unwrap();
+
+        // weekly meeting on Mondays 10:00 - 11:00, starting on the 2nd
+        let mut meeting = Event::new("Weekly".into(), &nd.with_day(2).unwrap())
+            .set_start(at(2, 10))
+            .unwrap()
+            .set_end(at(2, 11))
+            .unwrap();
+        meeting
+            .set_recurrence(Some(Recurrence::new(RecurrenceRule::Weekly {
+                interval: 1,
+                by_day: vec![],
+            })))
+            .unwrap();
+        let meeting_id = *meeting.id();
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(meeting);
+
+        // collides with the third occurrence on the 16th
+        let candidate = Event::new("Dentist".into(), &nd.with_day(16).unwrap())
+            .set_start(at(16, 10))
+            .unwrap()
+            .set_end(at(16, 12))
+            .unwrap();
+
+        let window = (
+            first_day_2023_ndt(),
+            NaiveDateTime::new(nd.with_day(31).unwrap(), day_end()),
+        );
+        assert_eq!(
+            cal.conflicts_expanded(&candidate, window),
+            vec![(meeting_id, at(16, 10))]
+        );
+
+        // outside the window nothing is reported
+        let window = (
+            first_day_2023_ndt(),
+            NaiveDateTime::new(nd.with_day(10).unwrap(), day_end()),
+        );
+        assert!(cal.conflicts_expanded(&candidate, window).is_empty());
+    }
+
+    #[test]
+    fn test_iter_days() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("A".into(), &nd.with_day(2).unwrap()));
+        cal.add_event(Event::new("B".into(), &nd.with_day(4).unwrap()));
+        cal.add_event(Event::new("C".into(), &nd.with_day(4).unwrap()));
+        cal.add_event(Event::new("D".into(), &nd.with_day(9).unwrap()));
+
+        let days: Vec<_> = cal
+            .iter_days(nd, nd.with_day(5).unwrap())
+            .map(|(date, evts)| {
+                let mut names: Vec<_> = evts.iter().map(|e| e.name()).collect();
+                names.sort();
+                (date.day(), names)
+            })
+            .collect();
+
+        assert_eq!(
+            days,
+            vec![
+                (1, vec![]),
+                (2, vec!["A"]),
+                (3, vec![]),
+                (4, vec!["B", "C"]),
+                (5, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlaps_with_buffer() {
+        let nd = first_day_2023_nd();
+        let timed = |name: &str, (sh, sm), (eh, em)| {
+            Event::new(name.into(), &nd)
+                .set_start_time(NaiveTime::from_hms_opt(sh, sm, 0).unwrap())
+                .unwrap()
+                .set_end_time(NaiveTime::from_hms_opt(eh, em, 0).unwrap())
+                .unwrap()
+        };
+
+        // 10 minutes between the two meetings
+        let first = timed("A", (9, 0), (10, 0));
+        let second = timed("B", (10, 10), (11, 0));
+        let fifteen = chrono::Duration::minutes(15);
+
+        assert!(!first.overlaps(&second));
+        assert!(first.overlaps_with_buffer(&second, fifteen));
+        assert!(second.overlaps_with_buffer(&first, fifteen));
+        assert!(!first.overlaps_with_buffer(&second, chrono::Duration::minutes(10)));
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(first);
+        assert!(cal.conflicts(&second).is_empty());
+        assert_eq!(cal.conflicts_with_buffer(&second, fifteen).len(), 1);
+    }
+
+    #[test]
+    fn test_export_range_ics() {
+        let nd = first_day_2023_nd();
+
+        let mut daily = Event::new("Daily".into(), &nd)
+            .set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+            .unwrap()
+            .set_end_time(NaiveTime::from_hms_opt(9, 15, 0).unwrap())
+            .unwrap();
+        daily
+            .set_recurrence(Some(Recurrence::new(RecurrenceRule::Daily { interval: 1 })))
+            .unwrap();
+        let daily_id = *daily.id();
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("Before".into(), &nd));
+        cal.add_event(Event::new("Inside".into(), &nd.with_day(3).unwrap()));
+        cal.add_event(Event::new("After".into(), &nd.with_day(10).unwrap()));
+        cal.add_event(daily);
+
+        let start = NaiveDateTime::new(nd.with_day(2).unwrap(), day_start());
+        let end = NaiveDateTime::new(nd.with_day(4).unwrap(), day_start());
+        let ics = cal.export_range_ics(start, end);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("SUMMARY:Inside\r\n"));
+        assert!(!ics.contains("SUMMARY:Before\r\n"));
+        assert!(!ics.contains("SUMMARY:After\r\n"));
+
+        // the daily event is expanded into the occurrences on the 2nd and 3rd
+        assert_eq!(ics.matches("SUMMARY:Daily\r\n").count(), 2);
+        assert_eq!(ics.matches(&format!("RELATED-TO:{daily_id}")).count(), 2);
+        assert!(!ics.contains(&format!("UID:{daily_id}")));
+        assert_eq!(cal.export_range_ics(start, end), ics);
+
+        // importing the export gives back each occurrence
+        let imported = EventCalendar::from_ics(&ics).unwrap();
+        let starts: Vec<_> = imported
+            .iter()
+            .filter(|evt| evt.name() == "Daily")
+            .map(|evt| evt.start())
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                nd.with_day(2).unwrap().and_hms_opt(9, 0, 0).unwrap(),
+                nd.with_day(3).unwrap().and_hms_opt(9, 0, 0).unwrap(),
+            ]
+        );
+        assert_eq!(imported.iter().count(), 3);
+    }
+
+    #[test]
+    fn test_event_merge_json() {
+        let nd = first_day_2023_nd();
+        let e = Event::new("A".into(), &nd);
+
+        let e = e
+            .merge_json(&serde_json::json!({"name": "X", "unknown": 1}))
+            .unwrap();
+        assert_eq!(e.name(), "X");
+        assert_eq!(e.start(), first_day_2023_ndt());
+
+        let e = e
+            .merge_json(&serde_json::json!({"start": "2023-01-01T09:30:00", "end": null}))
+            .unwrap();
+        assert_eq!(e.start(), nd.and_hms_opt(9, 30, 0).unwrap());
+
+        // a start after the end is rejected
+        let invalid = e
+            .clone()
+            .merge_json(&serde_json::json!({"start": "2023-01-05T00:00:00"}));
+        assert!(matches!(invalid, Err(EventError::InvalidStartTime)));
+
+        // as are values of the wrong type and non objects
+        let wrong_type = e.clone().merge_json(&serde_json::json!({"start": 5}));
+        assert!(matches!(wrong_type, Err(EventError::InvalidPatch(_))));
+        assert!(e.merge_json(&serde_json::json!([1, 2])).is_err());
+    }
+
+    #[test]
+    fn test_sorted_by_duration() {
+        let nd = first_day_2023_nd();
+        let ending_at = |name: &str, h| {
+            Event::new(name.into(), &nd)
+                .set_end_time(NaiveTime::from_hms_opt(h, 0, 0).unwrap())
+                .unwrap()
+        };
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(ending_at("Short", 1));
+        cal.add_event(ending_at("Long", 12));
+        cal.add_event(ending_at("Medium", 6));
+
+        let names: Vec<_> = cal
+            .sorted_by(|a, b| (b.end() - b.start()).cmp(&(a.end() - a.start())))
+            .iter()
+            .map(|e| e.name())
+            .collect();
+        assert_eq!(names, vec!["Long", "Medium", "Short"]);
+    }
+
+    #[test]
+    fn test_day_bounds_in_tz() {
+        use chrono::TimeZone;
+        use chrono_tz::America::{New_York, Sao_Paulo};
+
+        // clocks spring forward, so the day is 23 hours long
+        let date = NaiveDate::from_ymd_opt(2023, 3, 12).unwrap();
+        let start = day_start_in_tz(date, New_York);
+        let end = day_end_in_tz(date, New_York);
+        assert_eq!(
+            start,
+            New_York.with_ymd_and_hms(2023, 3, 12, 0, 0, 0).unwrap()
+        );
+        assert_eq!(end.naive_local(), date.and_time(day_end()));
+        assert_eq!((end - start).num_seconds() + 1, 23 * 3600);
+
+        // clocks fall back, so the day is 25 hours long
+        let date = NaiveDate::from_ymd_opt(2023, 11, 5).unwrap();
+        let start = day_start_in_tz(date, New_York);
+        let end = day_end_in_tz(date, New_York);
+        assert_eq!((end - start).num_seconds() + 1, 25 * 3600);
+
+        // midnight did not exist in Sao Paulo on this date
+        let date = NaiveDate::from_ymd_opt(2018, 11, 4).unwrap();
+        let start = day_start_in_tz(date, Sao_Paulo);
+        assert_eq!(start.naive_local(), date.and_hms_opt(1, 0, 0).unwrap());
+        assert_eq!(
+            (day_end_in_tz(date, Sao_Paulo) - start).num_seconds() + 1,
+            23 * 3600
+        );
+    }
+
+    #[test]
+    fn test_store_move_event() {
+        let nd = first_day_2023_nd();
+        let e = Event::new("A".into(), &nd);
+        let e_id = *e.id();
+
+        let mut work = EventCalendar::default();
+        work.add_event(e);
+
+        let mut store = CalendarStore::default();
+        let work_id = store.add_calendar(work);
+        let home_id = store.add_calendar(EventCalendar::default());
+
+        store.move_event(work_id, home_id, e_id).unwrap();
+        assert!(store.get(work_id).unwrap().get(e_id).is_none());
+        assert_eq!(
+            store.get(home_id).unwrap().get(e_id).map(|e| e.name()),
+            Some("A")
+        );
+
+        // moving it again from the old calendar fails and changes nothing
+        let missing = store.move_event(work_id, home_id, e_id);
+        assert!(matches!(missing, Err(StoreError::EventNotFound(id)) if id == e_id));
+        assert!(store.get(home_id).unwrap().get(e_id).is_some());
+
+        let unknown = Uuid::new_v4();
+        let missing = store.move_event(home_id, unknown, e_id);
+        assert!(matches!(missing, Err(StoreError::CalendarNotFound(id)) if id == unknown));
+        assert!(store.get(home_id).unwrap().get(e_id).is_some());
+    }
+
+    #[test]
+    fn test_weekly_recurrence_by_day() {
+        use chrono::Weekday;
+
+        // Monday the 2nd, 09:00 - 10:00
+        let nd = first_day_2023_nd().with_day(2).unwrap();
+        let mut e = Event::new("Gym".into(), &nd)
+            .set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+            .unwrap()
+            .set_end_time(NaiveTime::from_hms_opt(10, 0, 0).unwrap())
+            .unwrap();
+        e.set_recurrence(Some(Recurrence::new(RecurrenceRule::Weekly {
+            interval: 1,
+            by_day: vec![Weekday::Fri, Weekday::Mon, Weekday::Wed],
+        })))
+        .unwrap();
+
+        // two full weeks, Monday the 2nd up to and including Sunday the 15th
+        let end = NaiveDateTime::new(nd.with_day(15).unwrap(), day_end());
+        let occs = e
+            .occurrences_between(NaiveDateTime::new(nd, day_start()), end)
+            .unwrap();
+        let days: Vec<_> = occs.iter().map(|o| o.start().day()).collect();
+        assert_eq!(days, vec![2, 4, 6, 9, 11, 13]);
+        assert!(occs
+            .iter()
+            .all(|o| o.start().time() == NaiveTime::from_hms_opt(9, 0, 0).unwrap()));
+
+        // days earlier in the week than the first occurrence are skipped in the
+        // first week and count only counts real occurrences
+        let mut e = e.shift(chrono::Duration::days(2));
+        e.set_recurrence(Some(
+            Recurrence::new(RecurrenceRule::Weekly {
+                interval: 2,
+                by_day: vec![Weekday::Mon, Weekday::Wed],
+            })
+            .with_count(3),
+        ))
+        .unwrap();
+        let days: Vec<_> = e
+            .occurrences_between(
+                NaiveDateTime::new(nd, day_start()),
+                end + chrono::Duration::weeks(4),
+            )
+            .unwrap()
+            .iter()
+            .map(|o| o.start().date())
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                nd.with_day(4).unwrap(),
+                nd.with_day(16).unwrap(),
+                nd.with_day(18).unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calendar_compact() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let mut ids = Vec::new();
+        for day in 1..=20 {
+            let e = Event::new(day.to_string(), &nd.with_day(day).unwrap());
+            ids.push(*e.id());
+            cal.add_event(e);
+        }
+
+        // remove every other event
+        for id in ids.iter().step_by(2) {
+            cal.remove_event(*id);
+        }
+        let before: Vec<Event> = cal.iter().cloned().collect();
+
+        cal.compact();
+        assert!(cal.validate());
+        assert_eq!(cal.iter().cloned().collect::<Vec<_>>(), before);
+        for id in ids.iter().skip(1).step_by(2) {
+            assert!(cal.get(*id).is_some());
+        }
+    }
+
+    #[test]
+    fn test_iter_by_start_tiebreak() {
+        let with_uid = |uid: &str, name: &str| {
+            Event::from_ics_vevent(&format!(
+                "BEGIN:VEVENT\nUID:{uid}\nDTSTART:20230101T090000\nDTEND:20230101T100000\nSUMMARY:{name}\nEND:VEVENT"
+            ))
+            .unwrap()
+        };
+
+        // same start and end, so the derived Ord falls back to the name while
+        // iter_by_start falls back to the id
+        let low_id = with_uid("00000000-0000-4000-8000-000000000001", "Zebra");
+        let high_id = with_uid("ffffffff-ffff-4fff-bfff-ffffffffffff", "Aardvark");
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(high_id);
+        cal.add_event(low_id);
+        cal.add_event(Event::new(
+            "Later".into(),
+            &first_day_2023_nd().with_day(2).unwrap(),
+        ));
+
+        let by_ord: Vec<_> = cal.iter().map(|e| e.name()).collect();
+        let by_start: Vec<_> = cal.iter_by_start().map(|e| e.name()).collect();
+        assert_eq!(by_ord, vec!["Aardvark", "Zebra", "Later"]);
+        assert_eq!(by_start, vec!["Zebra", "Aardvark", "Later"]);
+        assert!(cal.validate());
+    }
+
+    #[test]
+    fn test_occurrences_carry_master_id() {
+        let nd = first_day_2023_nd();
+        let mut master = Event::new("Daily".into(), &nd);
+        master
+            .set_recurrence(Some(
+                Recurrence::new(RecurrenceRule::Daily { interval: 1 }).with_count(3),
+            ))
+            .unwrap();
+        let master_id = *master.id();
+        assert_eq!(master.master_id(), None);
+
+        let end = NaiveDateTime::new(nd.with_day(10).unwrap(), day_end());
+        let occs = master
+            .occurrences_between(first_day_2023_ndt(), end)
+            .unwrap();
+        assert_eq!(occs.len(), 3);
+        for occ in &occs {
+            assert_eq!(occ.master_id(), Some(&master_id));
+            assert_ne!(occ.id(), &master_id);
+            assert!(occ.recurrence().is_none());
+        }
+
+        // exported occurrences keep the UID of the series
+        assert!(occs[1]
+            .to_ics_vevent()
+            .contains(&format!("UID:{master_id}\r\n")));
+
+        // standalone events are not linked to anything
+        let single = Event::new("Once".into(), &nd);
+        let occs = single
+            .occurrences_between(first_day_2023_ndt(), end)
+            .unwrap();
+        assert_eq!(occs[0].master_id(), None);
+    }
+
+    #[test]
+    fn test_events_today() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("A".into(), &nd));
+        cal.add_event(
+            Event::new("B".into(), &nd)
+                .set_end(nd.with_day(2).unwrap().and_hms_opt(2, 0, 0).unwrap())
+                .unwrap(),
+        );
+
+        let clock = FixedClock(nd.and_hms_opt(15, 0, 0).unwrap());
+        let names: Vec<_> = cal.events_today(&clock).iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["A", "B"]);
+
+        let clock = FixedClock(nd.with_day(2).unwrap().and_hms_opt(8, 0, 0).unwrap());
+        let names: Vec<_> = cal.events_today(&clock).iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["B"]);
+
+        let clock = FixedClock(nd.with_day(3).unwrap().and_hms_opt(8, 0, 0).unwrap());
+        assert!(cal.events_today(&clock).is_empty());
+    }
+
+    #[test]
+    fn test_event_id_serialized_hyphenated() {
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let e = Event::new("Test".into(), &first_day_2023_nd());
+        let json = e.serialize().replace(&e.id().to_string(), id);
+        assert!(json.contains(&format!(r#""id":"{id}""#)));
+
+        let parsed: Event = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.id().to_string(), id);
+
+        // non hyphenated forms are still accepted when reading
+        let simple = json.replace(id, "67e5504410b1426f9247bb680e5fe0c8");
+        let parsed: Event = serde_json::from_str(&simple).unwrap();
+        assert_eq!(parsed.id().to_string(), id);
+        assert!(serde_json::from_str::<Event>(&json.replace(id, "not-a-uuid")).is_err());
+    }
+
+    #[test]
+    fn test_events_in_range_excluding() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let e1 = Event::new("Edited".into(), &nd);
+        let e1_id = *e1.id();
+        let e2 = Event::new("Other".into(), &nd);
+        cal.add_event(e1);
+        cal.add_event(e2);
+
+        let start = nd.and_hms_opt(0, 0, 0).unwrap();
+        let end = nd.and_hms_opt(23, 59, 59).unwrap();
+        assert_eq!(cal.events_in_range(start, end).count(), 2);
+
+        let rest: Vec<_> = cal.events_in_range_excluding(start, end, e1_id).collect();
+        assert_eq!(rest.len(), 1);
+        assert!(rest.iter().all(|evt| *evt.id() != e1_id));
+    }
+
+    #[test]
+    fn test_cmp_chronological() {
+        let nd = first_day_2023_nd();
+        let at = |h| nd.and_hms_opt(h, 0, 0).unwrap();
+        let e1 = Event::new("C".into(), &nd).set_end(at(10)).unwrap();
+        let e2 = Event::new("B".into(), &nd).set_end(at(12)).unwrap();
+        let e3 = Event::new("A".into(), &nd)
+            .set_end(at(18))
+            .unwrap()
+            .set_start(at(14))
+            .unwrap();
+
+        let mut evts = [e3.clone(), e1.clone(), e2.clone()];
+        evts.sort_by(Event::cmp_chronological);
+        let ids: Vec<_> = evts.iter().map(|e| *e.id()).collect();
+        assert_eq!(ids, vec![*e1.id(), *e2.id(), *e3.id()]);
+
+        // same start and end compare equal regardless of name
+        let other = Event::new("Z".into(), &nd).set_end(at(10)).unwrap();
+        assert_eq!(
+            Event::cmp_chronological(&e1, &other),
+            std::cmp::Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_drain() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in [3, 1, 2] {
+            cal.add_event(Event::new(format!("Day {day}"), &nd.with_day(day).unwrap()));
+        }
+
+        let drained = cal.drain();
+        assert_eq!(drained.len(), 3);
+        assert!(drained.windows(2).all(|w| w[0].start() <= w[1].start()));
+
+        assert_eq!(cal.iter().count(), 0);
+        assert_eq!(cal.iter_by_start().count(), 0);
+        assert!(cal.get(drained[0].id()).is_none());
+        assert!(cal.validate());
+    }
+
+    #[test]
+    fn test_set_start_end_mut() {
+        let nd = first_day_2023_nd();
+        let mut e = Event::new("Test".into(), &nd);
+
+        e.set_start_mut(nd.and_hms_opt(9, 0, 0).unwrap()).unwrap();
+        e.set_end_mut(nd.and_hms_opt(10, 0, 0).unwrap()).unwrap();
+        assert_eq!(e.start(), nd.and_hms_opt(9, 0, 0).unwrap());
+        assert_eq!(e.end(), nd.and_hms_opt(10, 0, 0).unwrap());
+
+        let before = e.clone();
+        assert!(matches!(
+            e.set_start_mut(nd.and_hms_opt(11, 0, 0).unwrap()),
+            Err(EventError::InvalidStartTime)
+        ));
+        assert_eq!(e, before);
+        assert!(matches!(
+            e.set_end_mut(nd.and_hms_opt(8, 0, 0).unwrap()),
+            Err(EventError::InvalidEndTime)
+        ));
+        assert_eq!(e, before);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+        let mut cal = EventCalendar::default();
+        cal.add_event(
+            Event::new("Standup".into(), &nd)
+                .set_end(at(10, 0))
+                .unwrap()
+                .set_start(at(9, 0))
+                .unwrap(),
+        );
+        cal.add_event(
+            Event::new("Lunch".into(), &nd)
+                .set_end(at(13, 0))
+                .unwrap()
+                .set_start(at(11, 0))
+                .unwrap(),
+        );
+
+        // an hour fits between the two events
+        let id = cal
+            .schedule("Review".into(), chrono::Duration::hours(1), at(9, 30), None)
+            .unwrap();
+        let evt = cal.get(id).unwrap();
+        assert_eq!((evt.start(), evt.end()), (at(10, 0), at(11, 0)));
+
+        // the gap is now taken, so the next slot is after lunch
+        let hours = WorkingHours::new(
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        );
+        let id = cal
+            .schedule(
+                "Sync".into(),
+                chrono::Duration::hours(4),
+                at(8, 0),
+                Some(hours),
+            )
+            .unwrap();
+        assert_eq!(cal.get(id).unwrap().start(), at(13, 0));
+
+        // does not fit in the rest of the day, so moved to the next morning
+        let id = cal
+            .schedule(
+                "Long".into(),
+                chrono::Duration::hours(2),
+                at(16, 0),
+                Some(hours),
+            )
+            .unwrap();
+        let next_day = nd.succ_opt().unwrap().and_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(cal.get(id).unwrap().start(), next_day);
+
+        assert!(cal
+            .schedule(
+                "Too long".into(),
+                chrono::Duration::hours(9),
+                at(8, 0),
+                Some(hours)
+            )
+            .is_none());
+        assert_eq!(cal.iter().count(), 5);
+    }
+
+    #[test]
+    fn test_to_utc_across_dst() {
+        use chrono::{TimeZone, Utc};
+        use chrono_tz::America::New_York;
+
+        let utc = |y, mo, d, h, mi| Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap();
+        let local = |mo, d, h, mi| {
+            NaiveDate::from_ymd_opt(2023, mo, d)
+                .unwrap()
+                .and_hms_opt(h, mi, 0)
+                .unwrap()
+        };
+        let event = |start: NaiveDateTime, end| {
+            let mut e = Event::new("Test".into(), &start.date())
+                .set_end(end)
+                .unwrap()
+                .set_start(start)
+                .unwrap();
+            e.set_zone(Some(New_York));
+            e
+        };
+
+        // floating events have no UTC equivalent
+        assert!(Event::new("Floating".into(), &first_day_2023_nd())
+            .to_utc()
+            .is_none());
+
+        // spans the spring forward, the end is already in daylight time
+        let e = event(local(3, 12, 1, 30), local(3, 12, 3, 30));
+        assert_eq!(e.zone(), Some(New_York));
+        assert_eq!(
+            e.to_utc(),
+            Some((utc(2023, 3, 12, 6, 30), utc(2023, 3, 12, 7, 30)))
+        );
+
+        // 02:30 does not exist that day and is read as standard time
+        let e = event(local(3, 12, 2, 30), local(3, 12, 4, 0));
+        assert_eq!(e.to_utc().unwrap().0, utc(2023, 3, 12, 7, 30));
+
+        // 01:30 happens twice when falling back, the earlier one is used
+        let e = event(local(11, 5, 1, 30), local(11, 5, 3, 0));
+        assert_eq!(
+            e.to_utc(),
+            Some((utc(2023, 11, 5, 5, 30), utc(2023, 11, 5, 8, 0)))
+        );
+
+        // events with only an offset use it directly
+        let ics = "BEGIN:VEVENT\r\nDTSTART:20230101T100000+0200\r\n\
+                   DTEND:20230101T110000+0200\r\nSUMMARY:Offset\r\nEND:VEVENT\r\n";
+        let e = Event::from_ics_vevent(ics).unwrap();
+        assert_eq!(
+            e.to_utc(),
+            Some((utc(2023, 1, 1, 8, 0), utc(2023, 1, 1, 9, 0)))
+        );
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+        let mut cal = EventCalendar::default();
+        for (start, end) in [
+            (at(9, 0), at(10, 0)),
+            (at(10, 15), at(12, 0)),
+            (at(14, 0), at(15, 0)),
+        ] {
+            cal.add_event(
+                Event::new("Busy".into(), &nd)
+                    .set_end(end)
+                    .unwrap()
+                    .set_start(start)
+                    .unwrap(),
+            );
+        }
+
+        let gaps = cal.find_gaps(at(8, 0), at(17, 0), chrono::Duration::zero());
+        assert_eq!(
+            gaps,
+            vec![
+                (at(8, 0), at(9, 0)),
+                (at(10, 0), at(10, 15)),
+                (at(12, 0), at(14, 0)),
+                (at(15, 0), at(17, 0)),
+            ]
+        );
+
+        // the 15 minute and one hour gaps are too short
+        let gaps = cal.find_gaps(at(8, 0), at(17, 0), chrono::Duration::hours(2));
+        assert_eq!(gaps, vec![(at(12, 0), at(14, 0)), (at(15, 0), at(17, 0))]);
+
+        // a window inside an event has no gaps
+        assert!(cal
+            .find_gaps(at(10, 30), at(11, 30), chrono::Duration::zero())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_query() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in 1..=6 {
+            let mut e = Event::new(format!("Meeting {day}"), &nd.with_day(day).unwrap());
+            if day % 2 == 0 {
+                e.add_tag("work".into());
+            }
+            cal.add_event(e);
+        }
+        let mut e = Event::new("Lunch".into(), &nd.with_day(2).unwrap());
+        e.add_tag("work".into());
+        cal.add_event(e);
+
+        let start = nd.with_day(2).unwrap().and_time(first_time_nt());
+        let end = nd.with_day(6).unwrap().and_time(last_time_nt());
+        let names: Vec<_> = Query::new()
+            .in_range(start, end)
+            .with_tag("work")
+            .limit(2)
+            .collect(&cal)
+            .iter()
+            .map(|e| e.name())
+            .collect();
+        assert_eq!(names, vec!["Lunch", "Meeting 2"]);
+
+        let found = Query::new()
+            .in_range(start, end)
+            .with_tag("work")
+            .named("Meeting")
+            .collect(&cal);
+        assert_eq!(found.len(), 3);
+
+        assert_eq!(Query::new().collect(&cal).len(), 7);
+        assert!(Query::new().with_tag("home").collect(&cal).is_empty());
+    }
+
+    #[test]
+    fn test_event_serialize_compact() {
+        let e = Event::new("Test".into(), &first_day_2023_nd());
+        // fields keep the order of serialize, start first
+        assert_eq!(
+            e.serialize_compact(),
+            format!(
+                r#"{{"start":"2023-01-01T00:00:00","end":"2023-01-01T23:59:59","name":"Test","id":"{}"}}"#,
+                e.id()
+            )
+        );
+
+        let mut tagged = e.clone();
+        tagged.add_tag("work".into());
+        assert_eq!(
+            tagged.serialize_compact(),
+            format!(
+                r#"{{"start":"2023-01-01T00:00:00","end":"2023-01-01T23:59:59","name":"Test","id":"{}","tags":["work"]}}"#,
+                e.id()
+            )
+        );
+
+        let parsed: Event = serde_json::from_str(&e.serialize_compact()).unwrap();
+        assert_eq!((parsed.id(), parsed.start()), (e.id(), e.start()));
+    }
+
+    #[test]
+    fn test_occurrences_on() {
+        // 2023-01-02 is a Monday
+        let monday = first_day_2023_nd().with_day(2).unwrap();
+        let at = |date: NaiveDate, h| date.and_hms_opt(h, 0, 0).unwrap();
+        let mut cal = EventCalendar::default();
+
+        let mut weekly = Event::new("Standup".into(), &monday)
+            .set_end(at(monday, 10))
+            .unwrap()
+            .set_start(at(monday, 9))
+            .unwrap();
+        weekly
+            .set_recurrence(Some(Recurrence::new(RecurrenceRule::Weekly {
+                interval: 1,
+                by_day: vec![],
+            })))
+            .unwrap();
+        let weekly_id = *weekly.id();
+        cal.add_event(weekly);
+
+        let next_monday = monday + chrono::Duration::weeks(2);
+        cal.add_event(Event::new("Holiday".into(), &next_monday));
+        cal.add_event(
+            Event::new("Dentist".into(), &next_monday)
+                .set_end(at(next_monday, 9))
+                .unwrap()
+                .set_start(at(next_monday, 8))
+                .unwrap(),
+        );
+        cal.add_event(Event::new(
+            "Other day".into(),
+            &next_monday.succ_opt().unwrap(),
+        ));
+
+        let occs = cal.occurrences_on(next_monday);
+        let names: Vec<_> = occs.iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["Holiday", "Dentist", "Standup"]);
+        assert_eq!(occs[2].start(), at(next_monday, 9));
+        assert_eq!(occs[2].master_id(), Some(&weekly_id));
+
+        // tuesdays only have the one-off event
+        let names: Vec<_> = cal
+            .occurrences_on(next_monday.succ_opt().unwrap())
+            .iter()
+            .map(|e| e.name().to_string())
+            .collect();
+        assert_eq!(names, vec!["Other day"]);
+    }
+
+    #[test]
+    fn test_event_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashSet;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |e: &Event| {
+            let mut hasher = DefaultHasher::new();
+            e.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let e = Event::new("Test".into(), &first_day_2023_nd());
+        let mut set = HashSet::new();
+        assert!(set.insert(e.clone()));
+        assert!(!set.insert(e.clone()));
+        assert!(set.insert(Event::new("Test".into(), &first_day_2023_nd())));
+        assert_eq!(set.len(), 2);
+
+        // an edited copy hashes the same but is a different value
+        let mut renamed = e.clone();
+        renamed.set_name("Renamed".into());
+        assert_eq!(hash(&e), hash(&renamed));
+        assert_ne!(e, renamed);
+    }
+
+    #[test]
+    fn test_set_event() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let e = Event::new("Test".into(), &nd);
+        let id = *e.id();
+        assert!(cal.set_event(e.clone()).is_none());
+
+        let moved = e.clone().shift(chrono::Duration::days(1));
+        assert_eq!(cal.set_event(moved.clone()), Some(e));
+
+        assert_eq!(cal.iter().count(), 1);
+        assert_eq!(cal.iter().next(), Some(&moved));
+        assert_eq!(cal.get(id).unwrap().start(), moved.start());
+        assert!(cal
+            .events_in_range(first_day_2023_ndt(), nd.and_time(last_time_nt()))
+            .next()
+            .is_none());
+        assert!(cal.validate());
+    }
+
+    #[test]
+    fn test_events_at_time_of_day() {
+        let nd = first_day_2023_nd();
+        let at = |date: NaiveDate, h, m| date.and_hms_opt(h, m, 0).unwrap();
+        let make = |name: &str, start, end| {
+            Event::new(name.into(), &nd)
+                .set_end(end)
+                .unwrap()
+                .set_start(start)
+                .unwrap()
+        };
+        let day2 = nd.with_day(2).unwrap();
+        let mut cal = EventCalendar::default();
+        cal.add_event(make("Breakfast", at(nd, 8, 30), at(nd, 8, 45)));
+        cal.add_event(make("Late", at(day2, 9, 0), at(day2, 10, 0)));
+        cal.add_event(make("Night", at(nd, 23, 0), at(day2, 7, 30)));
+        cal.add_event(make("Evening", at(day2, 22, 0), at(day2, 23, 0)));
+
+        let morning = |from, to| {
+            cal.events_at_time_of_day(
+                NaiveTime::from_hms_opt(from, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(to, 0, 0).unwrap(),
+            )
+            .map(|e| e.name().to_string())
+            .collect::<Vec<_>>()
+        };
+        // touching the end of the window does not count
+        assert_eq!(morning(8, 9), vec!["Breakfast"]);
+        // an overnight event overlaps the early morning of the next day
+        assert_eq!(morning(6, 8), vec!["Night"]);
+        // windows may wrap past midnight
+        assert_eq!(morning(21, 1), vec!["Night", "Evening"]);
+    }
+
+    #[test]
+    fn test_suggest_non_overlapping() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+        let make = |start, end| {
+            Event::new("Busy".into(), &nd)
+                .set_end(end)
+                .unwrap()
+                .set_start(start)
+                .unwrap()
+        };
+        let e = make(at(9, 0), at(10, 0));
+        let a = make(at(9, 30), at(11, 0));
+        let b = make(at(10, 45), at(12, 0));
+        let c = make(at(13, 0), at(14, 0));
+        let d = make(at(14, 30), at(15, 0));
+
+        // pushed past the cluster of a and b, fits before c
+        let others = [&d, &c, &b, &a];
+        assert_eq!(
+            e.suggest_non_overlapping(&others, chrono::Duration::zero()),
+            at(12, 0)
+        );
+
+        // with a half hour gap it no longer fits before c or d
+        let gap = chrono::Duration::minutes(30);
+        assert_eq!(e.suggest_non_overlapping(&others, gap), at(15, 30));
+
+        // no conflicts keeps the current start
+        assert_eq!(e.suggest_non_overlapping(&[&d], gap), at(9, 0));
+        assert_eq!(e.suggest_non_overlapping(&[&e], gap), at(9, 0));
+    }
+
+    #[test]
+    fn test_to_pretty_json() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in [3, 1, 2] {
+            let mut e = Event::new(format!("Day {day}"), &nd.with_day(day).unwrap());
+            e.set_property("room".into(), "B".into()).unwrap();
+            e.set_property("floor".into(), "2".into()).unwrap();
+            e.add_tag("work".into());
+            cal.add_event(e);
+        }
+
+        let json = cal.to_pretty_json();
+        assert_eq!(json, cal.to_pretty_json());
+        assert!(json.starts_with("[\n  {\n    \"start\""));
+        assert!(json.find("Day 1").unwrap() < json.find("Day 2").unwrap());
+        assert!(json.find("\"floor\"").unwrap() < json.find("\"room\"").unwrap());
+
+        // reloading gives back byte for byte the same output
+        let reloaded = EventCalendar::load(json.as_bytes()).unwrap();
+        assert_eq!(reloaded.to_pretty_json(), json);
+    }
+
+    #[test]
+    fn test_new_event_default_duration() {
+        let start = first_day_2023_nd().and_hms_opt(14, 0, 0).unwrap();
+
+        let mut cal = EventCalendar::with_config(CalendarConfig {
+            default_duration: Some(chrono::Duration::minutes(30)),
+        });
+        let id = cal.new_event("Meeting".into(), start).unwrap();
+        let evt = cal.get(id).unwrap();
+        assert_eq!(evt.start(), start);
+        assert_eq!(
+            evt.end(),
+            first_day_2023_nd().and_hms_opt(14, 30, 0).unwrap()
+        );
+
+        // without a default duration events are all day
+        let mut cal = EventCalendar::default();
+        let id = cal.new_event("Holiday".into(), start).unwrap();
+        assert!(cal.get(id).unwrap().is_all_day());
+
+        let mut cal = EventCalendar::with_config(CalendarConfig {
+            default_duration: Some(chrono::Duration::zero()),
+        });
+        assert!(matches!(
+            cal.new_event("Empty".into(), start),
+            Err(EventError::InvalidDuration)
+        ));
+    }
+
+    #[test]
+    fn test_event_join() {
+        let nd = first_day_2023_nd();
+        let at = |h| nd.and_hms_opt(h, 0, 0).unwrap();
+        let make = |name: &str, start, end| {
+            Event::new(name.into(), &nd)
+                .set_end(end)
+                .unwrap()
+                .set_start(start)
+                .unwrap()
+        };
+        let e = make("First", at(9), at(10));
+
+        // touching
+        let joined = e.clone().join(make("Second", at(10), at(11))).unwrap();
+        assert_eq!((joined.start(), joined.end()), (at(9), at(11)));
+        assert_eq!((joined.name(), joined.id()), ("First", e.id()));
+
+        // overlapping, other starts first
+        let joined = e
+            .clone()
+            .join(make("Second", at(8), at(9) + chrono::Duration::minutes(30)));
+        let joined = joined.unwrap();
+        assert_eq!((joined.start(), joined.end()), (at(8), at(10)));
+
+        // disjoint
+        assert!(matches!(
+            e.join(make("Second", at(11), at(12))),
+            Err(EventError::Disjoint)
+        ));
+    }
+
+    #[test]
+    fn test_day_agenda() {
+        let nd = first_day_2023_nd();
+        let day2 = nd.with_day(2).unwrap();
+        let at = |date: NaiveDate, h, m| date.and_hms_opt(h, m, 0).unwrap();
+        let make = |name: &str, start, end| {
+            Event::new(name.into(), &nd)
+                .set_end(end)
+                .unwrap()
+                .set_start(start)
+                .unwrap()
+        };
+        let mut cal = EventCalendar::default();
+        cal.add_event(make("Standup", at(day2, 9, 0), at(day2, 9, 15)));
+        cal.add_event(make("Night shift", at(nd, 22, 0), at(day2, 6, 0)));
+        cal.add_event(Event::new("Bank holiday".into(), &day2));
+        cal.add_event(make("Lunch", at(day2, 12, 30), at(day2, 13, 30)));
+
+        let expected = "\
+Monday 2023-01-02
+  all day      Bank holiday
+  00:00-06:00  Night shift
+  09:00-09:15  Standup
+  12:30-13:30  Lunch
+";
+        assert_eq!(cal.day_agenda(day2), expected);
+        assert_eq!(
+            cal.day_agenda(nd.with_day(5).unwrap()),
+            "Thursday 2023-01-05\n  no events\n"
+        );
+    }
+
+    #[test]
+    fn test_overlap_queries_match_naive_scan() {
+        // small xorshift generator so the test is deterministic
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: i64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as i64
+        };
+
+        let base = first_day_2023_ndt();
+        for _ in 0..20 {
+            let mut cal = EventCalendar::default();
+            let mut ids = Vec::new();
+            for _ in 0..200 {
+                let start = base + chrono::Duration::minutes(next(60 * 24 * 30));
+                let end = start + chrono::Duration::minutes(1 + next(60 * 24 * 3));
+                let e = Event::new("Random".into(), &start.date())
+                    .set_end(end)
+                    .unwrap()
+                    .set_start(start)
+                    .unwrap();
+                ids.push(*e.id());
+                cal.add_event(e);
+            }
+            for id in ids.iter().step_by(7) {
+                cal.remove_event(id);
+            }
+            assert!(cal.validate());
+
+            for _ in 0..50 {
+                let start = base + chrono::Duration::minutes(next(60 * 24 * 32));
+                let end = start + chrono::Duration::minutes(next(60 * 24 * 2));
+
+                let naive: Vec<&Event> = cal
+                    .iter()
+                    .filter(|evt| {
+                        (evt.start() >= start && evt.start() <= end)
+                            || (evt.end() >= start && evt.end() <= end)
+                    })
+                    .collect();
+                assert_eq!(cal.events_in_range_vec(start, end), naive);
+
+                let candidate = Event::new("Candidate".into(), &start.date())
+                    .set_end(end + chrono::Duration::minutes(1))
+                    .unwrap()
+                    .set_start(start)
+                    .unwrap();
+                let buffer = chrono::Duration::minutes(next(120));
+                let naive: Vec<&Event> = cal
+                    .iter()
+                    .filter(|evt| evt.overlaps_with_buffer(&candidate, buffer))
+                    .collect();
+                assert_eq!(cal.conflicts_with_buffer(&candidate, buffer), naive);
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_is_valid() {
+        let nd = first_day_2023_nd();
+        assert!(Event::new("Valid".into(), &nd).is_valid());
+
+        let noon = nd.and_hms_opt(12, 0, 0).unwrap();
+        let backwards = Event::new_unchecked("Backwards".into(), noon, first_day_2023_ndt());
+        assert!(!backwards.is_valid());
+        assert!(!Event::new_unchecked("Empty".into(), noon, noon).is_valid());
+    }
+
+    #[test]
+    fn test_events_between_dates() {
+        let nd = first_day_2023_nd();
+        let day = |d| nd.with_day(d).unwrap();
+        let mut cal = EventCalendar::default();
+        for d in 1..=5 {
+            cal.add_event(Event::new(format!("Day {d}"), &day(d)));
+        }
+        cal.add_event(
+            Event::new("Late".into(), &day(4))
+                .set_start(day(4).and_hms_opt(23, 0, 0).unwrap())
+                .unwrap(),
+        );
+
+        let names: Vec<_> = cal
+            .events_between_dates(day(2), day(4))
+            .map(|e| e.name())
+            .collect();
+        assert_eq!(names, vec!["Day 2", "Day 3", "Day 4", "Late"]);
+        assert_eq!(cal.events_between_dates(day(6), day(9)).count(), 0);
+    }
+
+    #[test]
+    fn test_occurrences_between_cap() {
+        let mut e = Event::new("Daily".into(), &first_day_2023_nd());
+        e.set_recurrence(Some(Recurrence::new(RecurrenceRule::Daily { interval: 1 })))
+            .unwrap();
+
+        let start = first_day_2023_ndt();
+        let end = NaiveDate::from_ymd_opt(2123, 1, 1)
+            .unwrap()
+            .and_time(day_start());
+        assert!(matches!(
+            e.occurrences_between(start, end),
+            Err(RecurError::TooMany)
+        ));
+
+        // the lazy iterator has no cap
+        assert_eq!(e.occurrences_iter(start, end).take(20_000).count(), 20_000);
+
+        // a narrower window is fine
+        let end = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_time(day_start());
+        assert_eq!(e.occurrences_between(start, end).unwrap().len(), 365);
+    }
+
+    #[test]
+    fn test_next_events() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in 1..=5 {
+            cal.add_event(Event::new(format!("Day {day}"), &nd.with_day(day).unwrap()));
+        }
+
+        // events starting exactly at `after` are not included
+        let after = nd.with_day(2).unwrap().and_time(day_start());
+        let names: Vec<_> = cal.next_events(after, 3).iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["Day 3", "Day 4", "Day 5"]);
+
+        let after = nd.with_day(4).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        assert_eq!(cal.next_events(after, 3).len(), 1);
+        assert!(cal.next_events(first_day_2023_ndt(), 0).is_empty());
+    }
+
+    #[test]
+    fn test_event_serialize_field_order() {
+        let mut e = Event::new("Test".into(), &first_day_2023_nd());
+        e.set_property("room".into(), "B".into()).unwrap();
+        e.add_tag("work".into());
+        e.set_recurrence(Some(Recurrence::new(RecurrenceRule::Daily { interval: 1 })))
+            .unwrap();
+        e.set_zone(Some(chrono_tz::Europe::Paris));
+        let occ = e
+            .occurrences_between(
+                first_day_2023_ndt(),
+                first_day_2023_nd().and_time(day_end()),
+            )
+            .unwrap()
+            .remove(0);
+
+        let keys = |json: &str| {
+            let fields = [
+                "start",
+                "end",
+                "name",
+                "id",
+                "modified",
+                "offset",
+                "properties",
+                "tags",
+                "recurrence",
+                "master_id",
+                "zone",
+            ];
+            let mut found: Vec<_> = fields
+                .iter()
+                .filter_map(|key| json.find(&format!("\"{key}\":")).map(|pos| (pos, *key)))
+                .collect();
+            found.sort();
+            found.into_iter().map(|(_, key)| key).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            keys(&e.serialize()),
+            vec![
+                "start",
+                "end",
+                "name",
+                "id",
+                "modified",
+                "properties",
+                "tags",
+                "recurrence",
+                "zone"
+            ]
+        );
+        assert_eq!(
+            keys(&occ.serialize()),
+            vec![
+                "start",
+                "end",
+                "name",
+                "id",
+                "modified",
+                "properties",
+                "tags",
+                "master_id",
+                "zone"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_busy_on() {
+        let nd = first_day_2023_nd();
+        let day = |d| nd.with_day(d).unwrap();
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("Single".into(), &day(2)));
+        cal.add_event(
+            Event::new("Trip".into(), &day(5))
+                .set_end(day(8).and_hms_opt(12, 0, 0).unwrap())
+                .unwrap(),
+        );
+        // ends exactly at midnight so does not touch the 11th
+        cal.add_event(
+            Event::new("Night".into(), &day(10))
+                .set_end(day(11).and_time(day_start()))
+                .unwrap(),
+        );
+
+        assert!(cal.busy_on(day(2)));
+        assert!(!cal.busy_on(day(3)));
+        assert!(cal.busy_on(day(6)));
+        assert!(cal.busy_on(day(8)));
+        assert!(!cal.busy_on(day(9)));
+        assert!(!cal.busy_on(day(11)));
+    }
+
+    #[test]
+    fn test_ics_summary_escaped() {
+        let escaped =
+            |name: &str| Event::new(name.into(), &first_day_2023_nd()).ics_summary_escaped();
+
+        assert_eq!(escaped("Plain name"), "Plain name");
+        assert_eq!(escaped("a\\b"), "a\\\\b");
+        assert_eq!(escaped("a,b"), "a\\,b");
+        assert_eq!(escaped("a;b"), "a\\;b");
+        assert_eq!(escaped("a\nb"), "a\\nb");
+        assert_eq!(escaped("a\r\nb"), "a\\nb");
+
+        let e = Event::new("Lunch; pay, \\ later".into(), &first_day_2023_nd());
+        let summary = format!("SUMMARY:{}\r\n", e.ics_summary_escaped());
+        assert!(e.to_ics_vevent().contains(&summary));
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in 1..=4 {
+            cal.add_event(Event::new(format!("Day {day}"), &nd.with_day(day).unwrap()));
+        }
+
+        // move the first two events after the others and rename everything
+        for (i, mut evt) in cal.iter_mut().enumerate() {
+            if i < 2 {
+                *evt = evt.clone().shift(chrono::Duration::days(7));
+            }
+            let name = format!("{} (edited)", evt.name());
+            evt.set_name(name);
+        }
+
+        let names: Vec<_> = cal.iter().map(|e| e.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Day 3 (edited)",
+                "Day 4 (edited)",
+                "Day 1 (edited)",
+                "Day 2 (edited)"
+            ]
+        );
+        assert_eq!(
+            cal.iter_by_start().last().unwrap().start().date(),
+            nd.with_day(9).unwrap()
+        );
+        assert!(cal.validate());
+
+        // guards can be collected and still put their events back
+        let guards: Vec<_> = cal.iter_mut().collect();
+        assert_eq!(guards.len(), 4);
+        drop(guards);
+        assert_eq!(cal.iter().count(), 4);
+        assert!(cal.validate());
+    }
+
+    #[test]
+    fn test_timeline() {
+        let nd = first_day_2023_nd();
+        let at = |h| nd.and_hms_opt(h, 0, 0).unwrap();
+        let make = |start, end| {
+            Event::new("Busy".into(), &nd)
+                .set_end(end)
+                .unwrap()
+                .set_start(start)
+                .unwrap()
+        };
+        let a = make(at(9), at(12));
+        let b = make(at(10), at(11));
+        let c = make(at(12), at(14));
+        let d = make(at(6), at(7));
+        let (a_id, b_id, c_id) = (*a.id(), *b.id(), *c.id());
+        let mut cal = EventCalendar::default();
+        for e in [a, b, c, d] {
+            cal.add_event(e);
+        }
+
+        use TimelinePoint::*;
+        assert_eq!(
+            cal.timeline(at(8), at(13)),
+            vec![
+                (at(9), Starts(a_id)),
+                (at(10), Starts(b_id)),
+                (at(11), Ends(b_id)),
+                (at(12), Ends(a_id)),
+                (at(12), Starts(c_id)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_with_tags() {
+        let mut e = Event::new("Test".into(), &first_day_2023_nd());
+        e.add_tag("home".into());
+        let e = e.with_tags(["work", "urgent", "work", "home"].map(String::from));
+
+        let tags: Vec<_> = e.tags().iter().map(String::as_str).collect();
+        assert_eq!(tags, vec!["home", "urgent", "work"]);
+    }
+
+    #[test]
+    fn test_for_each_in_range() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in 1..=6 {
+            cal.add_event(Event::new(format!("Day {day}"), &nd.with_day(day).unwrap()));
+        }
+        let start = nd.with_day(2).unwrap().and_time(day_start());
+        let end = nd.with_day(5).unwrap().and_time(day_end());
+
+        let mut seen = Vec::new();
+        cal.for_each_in_range(start, end, |evt| {
+            seen.push(evt.name().to_string());
+            true
+        });
+        assert_eq!(seen, vec!["Day 2", "Day 3", "Day 4", "Day 5"]);
+
+        // stops right after the callback returns false
+        let mut calls = 0;
+        cal.for_each_in_range(start, end, |_| {
+            calls += 1;
+            calls < 2
+        });
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_monthly_recurrence_by_set_pos() {
+        let month_rule = |pos, day| {
+            Some(Recurrence::new(RecurrenceRule::Monthly {
+                interval: 1,
+                by_set_pos: Some((pos, day)),
+            }))
+        };
+        let nd = first_day_2023_nd();
+        let window = (
+            first_day_2023_ndt(),
+            nd.with_month(7).unwrap().and_time(day_start()),
+        );
+        let days = |e: &Event| {
+            e.occurrences_between(window.0, window.1)
+                .unwrap()
+                .iter()
+                .map(|occ| (occ.start().month(), occ.start().day()))
+                .collect::<Vec<_>>()
+        };
+
+        // 2nd Monday, at the time of the first occurrence
+        let mut e = Event::new("Book club".into(), &nd.with_day(9).unwrap())
+            .set_start_time(NaiveTime::from_hms_opt(18, 0, 0).unwrap())
+            .unwrap();
+        e.set_recurrence(month_rule(2, chrono::Weekday::Mon))
+            .unwrap();
+        assert_eq!(
+            days(&e),
+            vec![(1, 9), (2, 13), (3, 13), (4, 10), (5, 8), (6, 12)]
+        );
+        let occs = e.occurrences_between(window.0, window.1).unwrap();
+        assert!(occs.iter().all(|occ| occ.start().hour() == 18));
+
+        // last Friday
+        e.set_recurrence(month_rule(-1, chrono::Weekday::Fri))
+            .unwrap();
+        assert_eq!(
+            days(&e),
+            vec![(1, 27), (2, 24), (3, 31), (4, 28), (5, 26), (6, 30)]
+        );
+
+        // 5th Sunday only exists in January and April
+        e.set_recurrence(month_rule(5, chrono::Weekday::Sun))
+            .unwrap();
+        assert_eq!(days(&e), vec![(1, 29), (4, 30)]);
+
+        // positions no month has are rejected, leaving the rule unchanged
+        for pos in [0, 6, -6, i8::MIN] {
+            assert!(matches!(
+                e.set_recurrence(month_rule(pos, chrono::Weekday::Sun)),
+                Err(EventError::InvalidSetPos(p)) if p == pos
+            ));
+        }
+        assert_eq!(days(&e), vec![(1, 29), (4, 30)]);
+
+        // and can't be loaded or imported either
+        let json = e.serialize();
+        assert!(json.contains("\"by_set_pos\":[5,\"Sun\"]"));
+        assert!(serde_json::from_str::<Event>(&json.replace("[5,", "[6,")).is_err());
+        let block = e.to_ics_vevent().replace("BYDAY=5SU", "BYDAY=6SU");
+        assert!(matches!(
+            Event::from_ics_vevent(&block),
+            Err(IcsError::UnsupportedRecurrence(_))
+        ));
+    }
+
+    #[test]
+    fn test_reindex() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let e = Event::new("Test".into(), &nd);
+        let id = *e.id();
+        cal.add_event(e.clone());
+        cal.add_event(Event::new("Other".into(), &nd.with_day(3).unwrap()));
+
+        // edit the event behind the other indexes' back
+        let moved = e.shift(chrono::Duration::days(7));
+        cal.ids_mut().insert(id, std::rc::Rc::new(moved.clone()));
+        assert!(!cal.validate());
+
+        cal.reindex();
+        assert!(cal.validate());
+        let names: Vec<_> = cal.iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["Other", "Test"]);
+        assert_eq!(cal.next_events(first_day_2023_ndt(), 2)[1], &moved);
+    }
+
+    #[test]
+    fn test_days_spanned() {
+        let nd = first_day_2023_nd();
+        let day2 = nd.with_day(2).unwrap();
+        assert_eq!(Event::new("All day".into(), &nd).days_spanned(), 1);
+
+        let overnight = Event::new("Overnight".into(), &nd)
+            .set_end(day2.and_hms_opt(1, 0, 0).unwrap())
+            .unwrap()
+            .set_start(nd.and_hms_opt(23, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(overnight.days_spanned(), 2);
+
+        // ending exactly at midnight does not touch the next day
+        let to_midnight = Event::new("To midnight".into(), &nd)
+            .set_end(day2.and_time(day_start()))
+            .unwrap();
+        assert_eq!(to_midnight.days_spanned(), 1);
+
+        let week = Event::new("Week".into(), &nd)
+            .set_end_date(nd.with_day(7).unwrap())
+            .unwrap();
+        assert_eq!(week.days_spanned(), 7);
+    }
+
+    #[test]
+    fn test_peek_next_after() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        assert!(cal.peek_next_after(first_day_2023_ndt()).is_none());
+        for day in [4, 2, 3] {
+            cal.add_event(Event::new(format!("Day {day}"), &nd.with_day(day).unwrap()));
+        }
+
+        let after = nd.and_hms_opt(12, 0, 0).unwrap();
+        let next = cal.next_event_after(after).unwrap();
+        assert_eq!(next.name(), "Day 2");
+        assert_eq!(cal.peek_next_after(after), Some((*next.id(), next.start())));
+
+        let after = nd.with_day(4).unwrap().and_time(day_start());
+        assert!(cal.peek_next_after(after).is_none());
+    }
+
+    #[test]
+    fn test_open_ended_events() {
+        let nd = first_day_2023_nd();
+        let at = |d, h| nd.with_day(d).unwrap().and_hms_opt(h, 0, 0).unwrap();
+        let trip = Event::new_open_ended("Trip".into(), at(2, 9));
+        assert!(trip.is_open_ended());
+        assert_eq!(trip.fixed_end(), None);
+
+        let fixed = Event::new("Meeting".into(), &nd)
+            .set_end(at(2, 10))
+            .unwrap()
+            .set_start(at(2, 9))
+            .unwrap();
+        assert!(!fixed.is_open_ended());
+
+        // sorts after fixed events with the same start
+        let mut cal = EventCalendar::default();
+        cal.add_event(trip.clone());
+        cal.add_event(fixed.clone());
+        let names: Vec<_> = cal.iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["Meeting", "Trip"]);
+
+        // shows up in range queries starting before it and conflicts long after
+        assert_eq!(cal.events_in_range(at(1, 0), at(2, 12)).count(), 2);
+        let later = Event::new("Later".into(), &nd.with_day(20).unwrap());
+        assert_eq!(cal.conflicts(&later), vec![&trip]);
+        assert!(cal.busy_on(nd.with_day(25).unwrap()));
+        assert!(trip.overlaps_with_buffer(&later, chrono::Duration::hours(1)));
+        assert_eq!(
+            cal.day_agenda(nd.with_day(25).unwrap()),
+            "Wednesday 2023-01-25\n  all day      Trip\n"
+        );
+
+        // stays open-ended when moved and through a save/load round trip
+        assert!(trip
+            .clone()
+            .shift(chrono::Duration::days(-1))
+            .is_open_ended());
+        assert!(trip.serialize().contains(r#""end":null"#));
+        let reloaded = EventCalendar::load(cal.to_pretty_json().as_bytes()).unwrap();
+        assert!(reloaded.get(trip.id()).unwrap().is_open_ended());
+        assert!(!trip.to_ics_vevent().contains("DTEND"));
+
+        // setting an end closes it
+        let closed = trip.set_end(at(5, 18)).unwrap();
+        assert_eq!(closed.fixed_end(), Some(at(5, 18)));
+    }
+
+    #[test]
+    fn test_export_ics_where() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let mut work = Event::new("Standup".into(), &nd)
+            .set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+            .unwrap()
+            .set_end_time(NaiveTime::from_hms_opt(9, 15, 0).unwrap())
+            .unwrap()
+            .with_tags(["work".to_string()]);
+        work.set_recurrence(Some(Recurrence::new(RecurrenceRule::Daily { interval: 1 })))
+            .unwrap();
+        let home = Event::new("Laundry".into(), &nd).with_tags(["home".to_string()]);
+        let (work_id, home_id) = (*work.id(), *home.id());
+        cal.add_event(work);
+        cal.add_event(home);
+
+        let ics = cal.export_ics_where(|evt| evt.has_tag("work"));
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(!ics.contains(&format!("UID:{home_id}")));
+        assert!(!ics.contains("Laundry"));
+
+        // the recurring event is written once with its rule
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains(&format!("UID:{work_id}\r\n")));
+        assert!(ics.contains("RRULE:FREQ=DAILY\r\n"));
+        let back = EventCalendar::from_ics(&ics).unwrap();
+        assert_eq!(back.iter().count(), 1);
+        assert!(back.get(work_id).unwrap().recurrence().is_some());
+        assert!(!cal
+            .export_ics_where(|evt| evt.has_tag("home"))
+            .contains("Standup"));
+
+        // within a window it is expanded into its occurrences on the 1st to 3rd
+        let start = first_day_2023_ndt();
+        let end = NaiveDateTime::new(nd.with_day(4).unwrap(), day_start());
+        let ics = cal.export_range_ics_where(start, end, |evt| evt.has_tag("work"));
+        assert!(!ics.contains("Laundry"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+        assert_eq!(ics.matches(&format!("RELATED-TO:{work_id}")).count(), 3);
+        assert!(ics.contains("DTSTART:20230101T090000\r\n"));
+        assert!(ics.contains("DTSTART:20230103T090000\r\n"));
+    }
+
+    #[test]
+    fn test_event_summary() {
+        let nd = first_day_2023_nd();
+        let mut e = Event::new("Standup".into(), &nd).with_tags(["work".to_string()]);
+        e.set_property("room".into(), "B".into()).unwrap();
+        let mut cal = EventCalendar::default();
+        cal.add_event(e.clone());
+        cal.add_event(Event::new("Tomorrow".into(), &nd.with_day(2).unwrap()));
+
+        let summaries = cal.summaries_in_range(first_day_2023_ndt(), nd.and_time(day_end()));
+        assert_eq!(summaries, vec![e.summary()]);
+
+        let json: serde_json::Value = serde_json::to_value(&summaries[0]).unwrap();
+        let mut keys: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["end", "id", "name", "start"]);
+        assert_eq!(json["id"], e.id().to_string());
+        assert_eq!(json["name"], "Standup");
+    }
+
+    #[test]
+    fn test_from_ics_unfolds_lines() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   VERSION:2.0\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:8e3f1c52-1d4b-4c39-9a0e-6f2f6d8b1a77\r\n\
+                   DTSTART:20230101T090000\r\n\
+                   DTEND:20230101T100000\r\n\
+                   SUMMARY:Quarterly planning with the \r\n \
+                   whole team\\, including \r\n\
+                   \tremote folks\r\n\
+                   END:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+
+        let cal = EventCalendar::from_ics(ics).unwrap();
+        assert_eq!(cal.iter().count(), 1);
+        let evt = cal.iter().next().unwrap();
+        assert_eq!(
+            evt.name(),
+            "Quarterly planning with the whole team, including remote folks"
+        );
+        assert!(cal.validate());
+
+        // a block that never ends is rejected
+        assert!(EventCalendar::from_ics("BEGIN:VEVENT\r\nDTSTART:20230101T090000\r\n").is_err());
+    }
+
+    #[test]
+    fn test_events_in_range_by_end() {
+        let nd = first_day_2023_nd();
+        let at = |day: u32, hour: u32| nd.with_day(day).unwrap().and_hms_opt(hour, 0, 0).unwrap();
+
+        let mut cal = EventCalendar::default();
+        // starts in the window but wraps up after it
+        let spanning = Event::new_unchecked("Offsite".into(), at(2, 9), at(9, 17));
+        // started before the window and wraps up in it
+        let wrapping = Event::new_unchecked("Sprint".into(), at(1, 9), at(4, 17));
+        let short = Event::new_unchecked("Review".into(), at(3, 9), at(3, 10));
+        cal.add_event(spanning.clone());
+        cal.add_event(wrapping.clone());
+        cal.add_event(short.clone());
+
+        let (start, end) = (at(2, 0), at(8, 0));
+        let by_start: Vec<_> = cal.events_in_range(start, end).map(|e| *e.id()).collect();
+        assert_eq!(by_start, vec![*wrapping.id(), *spanning.id(), *short.id()]);
+
+        let by_end: Vec<_> = cal
+            .events_in_range_by_end(start, end)
+            .map(|e| *e.id())
+            .collect();
+        assert_eq!(by_end, vec![*short.id(), *wrapping.id()]);
+    }
+
+    #[test]
+    fn test_events_per_weekday() {
+        // 2023-01-01 is a Sunday
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        for day in [2, 3, 3, 10, 10, 17, 1, 8] {
+            cal.add_event(Event::new("evt".into(), &nd.with_day(day).unwrap()));
+        }
+
+        // Mon: 2nd, Tue: 3rd x2, 10th x2, 17th, Sun: 1st, 8th
+        assert_eq!(cal.events_per_weekday(), [1, 5, 0, 0, 0, 0, 2]);
+        assert_eq!(EventCalendar::default().events_per_weekday(), [0; 7]);
+    }
+
+    #[test]
+    fn test_overlaps_all_day() {
+        let nd = first_day_2023_nd();
+        let all_day = Event::new("Holiday".into(), &nd);
+        assert!(all_day.is_all_day());
+
+        let late = nd.and_hms_milli_opt(23, 59, 59, 500).unwrap();
+        let night =
+            Event::new_unchecked("Deploy".into(), late, late + chrono::Duration::minutes(30));
+        assert!(all_day.overlaps(&night));
+        assert!(night.overlaps(&all_day));
+
+        // starting at midnight is the next day, so still no overlap
+        let next = nd.succ_opt().unwrap().and_time(day_start());
+        let morning =
+            Event::new_unchecked("Breakfast".into(), next, next + chrono::Duration::hours(1));
+        assert!(!all_day.overlaps(&morning));
+        assert!(!morning.overlaps(&all_day));
+    }
+
+    #[test]
+    fn test_search_ranking() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let substring = Event::new("Weekly Standup notes".into(), &nd);
+        let exact = Event::new("Standup".into(), &nd.with_day(5).unwrap());
+        let prefix = Event::new("standup retro".into(), &nd.with_day(3).unwrap());
+        let tagged = Event::new("Sync".into(), &nd).with_tags(["standup".to_string()]);
+        cal.add_event(substring.clone());
+        cal.add_event(exact.clone());
+        cal.add_event(prefix.clone());
+        cal.add_event(tagged.clone());
+        cal.add_event(Event::new("Lunch".into(), &nd));
+
+        let hits = cal.search("STANDUP");
+        let ids: Vec<_> = hits.iter().map(|(evt, _)| *evt.id()).collect();
+        assert_eq!(
+            ids,
+            vec![*exact.id(), *prefix.id(), *substring.id(), *tagged.id()]
+        );
+        assert!(hits.windows(2).all(|w| w[0].1 > w[1].1));
+        assert_eq!(hits[0].1, 1.0);
+
+        assert!(cal.search("  ").is_empty());
+        assert!(cal.search("dentist").is_empty());
+    }
+
+    #[test]
+    fn test_override_occurrence() {
+        // weekly on Mondays at 9:00, starting Monday 2023-01-02
+        let nd = first_day_2023_nd().with_day(2).unwrap();
+        let at = |date: NaiveDate, hour| date.and_hms_opt(hour, 0, 0).unwrap();
+        let mut standup = Event::new_unchecked("Standup".into(), at(nd, 9), at(nd, 10));
+        standup
+            .set_recurrence(Some(
+                Recurrence::new(RecurrenceRule::Weekly {
+                    interval: 1,
+                    by_day: vec![],
+                })
+                .with_count(4),
+            ))
+            .unwrap();
+
+        // move the second occurrence from Monday the 9th to Friday the 13th
+        let moved_from = nd.with_day(9).unwrap();
+        let friday = nd.with_day(13).unwrap();
+        standup.override_occurrence(
+            moved_from,
+            Event::new_unchecked("Standup (moved)".into(), at(friday, 14), at(friday, 15)),
+        );
+
+        let month_end = nd.with_day(31).unwrap().and_time(day_end());
+        let occs = standup.occurrences_between(at(nd, 0), month_end).unwrap();
+        let starts: Vec<_> = occs.iter().map(|o| o.start()).collect();
+        assert_eq!(
+            starts,
+            vec![
+                at(nd, 9),
+                at(friday, 14),
+                at(nd.with_day(16).unwrap(), 9),
+                at(nd.with_day(23).unwrap(), 9)
+            ]
+        );
+        assert_eq!(occs[1].name(), "Standup (moved)");
+        assert_eq!(occs[1].master_id(), Some(standup.id()));
+        assert!(occs[1].recurrence().is_none());
+
+        // the moved occurrence is found on its new day but not its old one
+        let on = |date: NaiveDate| {
+            standup
+                .occurrences_between(at(date, 0), date.and_time(day_end()))
+                .unwrap()
+        };
+        assert!(on(moved_from).is_empty());
+        assert_eq!(on(friday).len(), 1);
+
+        // overrides survive a JSON round trip
+        let json = serde_json::to_string(&standup).unwrap();
+        let back: Event = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.overrides().len(), 1);
+    }
+
+    #[test]
+    fn test_has_conflict() {
+        let nd = first_day_2023_nd();
+        let at = |hour, min| nd.and_hms_opt(hour, min, 0).unwrap();
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new_unchecked("A".into(), at(9, 0), at(10, 0)));
+        cal.add_event(Event::new_unchecked("B".into(), at(13, 0), at(14, 0)));
+
+        let clash = Event::new_unchecked("C".into(), at(9, 30), at(11, 0));
+        assert!(cal.has_conflict(&clash));
+        assert_eq!(cal.conflicts(&clash).len(), 1);
+
+        // back to back with both existing events
+        let free = Event::new_unchecked("D".into(), at(10, 0), at(13, 0));
+        assert!(!cal.has_conflict(&free));
+        assert!(cal.conflicts(&free).is_empty());
+
+        // an event never conflicts with itself
+        let existing = cal.iter().next().unwrap().clone();
+        assert!(!cal.has_conflict(&existing));
+
+        // a late night event conflicts with an all day event on that date
+        let mut cal = EventCalendar::default();
+        cal.add_event(Event::new("Holiday".into(), &nd));
+        let late = nd.and_hms_milli_opt(23, 59, 59, 500).unwrap();
+        let night = Event::new_unchecked("Deploy".into(), late, late + chrono::Duration::hours(1));
+        assert!(cal.has_conflict(&night));
+        assert_eq!(cal.conflicts(&night).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_time_loose() {
+        let t = |h, m| NaiveTime::from_hms_opt(h, m, 0);
+
+        assert_eq!(parse_time_loose("9am"), t(9, 0));
+        assert_eq!(parse_time_loose("9 AM"), t(9, 0));
+        assert_eq!(parse_time_loose("12am"), t(0, 0));
+        assert_eq!(parse_time_loose("12pm"), t(12, 0));
+        assert_eq!(parse_time_loose("9:00 PM"), t(21, 0));
+        assert_eq!(parse_time_loose("09:00"), t(9, 0));
+        assert_eq!(parse_time_loose("14:30"), t(14, 30));
+        assert_eq!(parse_time_loose(" Noon "), t(12, 0));
+        assert_eq!(parse_time_loose("midnight"), t(0, 0));
+
+        for invalid in [
+            "", "9", "lunch", "13pm", "0am", "9:5", "24:00", "9:60", "+9:00", "9::00",
+        ] {
+            assert_eq!(parse_time_loose(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_clone_range() {
+        let nd = first_day_2023_nd();
+        let at = |day: u32, hour: u32| nd.with_day(day).unwrap().and_hms_opt(hour, 0, 0).unwrap();
+        let mut cal = EventCalendar::default();
+        let before = Event::new("Before".into(), &nd);
+        let spanning = Event::new_unchecked("Trip".into(), at(1, 12), at(9, 12));
+        let inside = Event::new("Inside".into(), &nd.with_day(3).unwrap());
+        let after = Event::new("After".into(), &nd.with_day(10).unwrap());
+        for evt in [&before, &spanning, &inside, &after] {
+            cal.add_event(evt.clone());
+        }
+
+        let mut sub = cal.clone_range(at(2, 0), at(5, 0));
+        let ids: Vec<_> = sub.iter().map(|e| *e.id()).collect();
+        assert_eq!(ids, vec![*spanning.id(), *inside.id()]);
+        assert_eq!(**sub.get(inside.id()).unwrap(), inside);
+        assert!(sub.validate());
+
+        // the original is left alone by changes to the copy
+        sub.remove_event(spanning.id());
+        assert_eq!(cal.iter().count(), 4);
+        assert!(cal.get(spanning.id()).is_some());
+    }
+
+    #[test]
+    fn test_event_color() {
+        assert_eq!(parse_color("#0a0"), Some(Color::rgb(0x00, 0xaa, 0x00)));
+        assert_eq!(parse_color("#1E90FF"), Some(Color::rgb(0x1e, 0x90, 0xff)));
+        assert_eq!(parse_color(" Teal "), Some(Color::rgb(0x00, 0x80, 0x80)));
+        assert_eq!(parse_color("#ABC").unwrap().to_hex(), "#aabbcc");
+        for garbage in [
+            "", "#", "#12", "#1234", "#12345g", "0a0a0a", "blurple", "#+1+2+3",
+        ] {
+            assert_eq!(parse_color(garbage), None, "{garbage}");
+        }
+
+        let mut evt = Event::new("Gym".into(), &first_day_2023_nd());
+        assert!(evt.color().is_none());
+        evt.set_color(Some("orange")).unwrap();
+        assert_eq!(evt.color().unwrap().to_hex(), "#ffa500");
+        assert!(matches!(
+            evt.set_color(Some("#nope")),
+            Err(EventError::InvalidColor(_))
+        ));
+        assert_eq!(evt.color().unwrap().to_hex(), "#ffa500");
+
+        let json = serde_json::to_value(&evt).unwrap();
+        assert_eq!(json["color"], "#ffa500");
+        let back: Event = serde_json::from_value(json).unwrap();
+        assert_eq!(back, evt);
+
+        evt.set_color(None).unwrap();
+        assert!(evt.color().is_none());
+        let evt = evt.with_color(Color::rgb(1, 2, 3));
+        assert_eq!(evt.color(), Some(Color::rgb(1, 2, 3)));
+    }
+
+    #[test]
+    fn test_events_in_range_tz() {
+        use chrono::TimeZone;
+        use chrono_tz::{America::New_York, Asia::Tokyo, UTC};
+
+        let nd = first_day_2023_nd();
+        let at = |hour| nd.and_hms_opt(hour, 0, 0).unwrap();
+        let zoned = |name: &str, hour, tz| {
+            let mut e = Event::new_unchecked(name.into(), at(hour), at(hour + 1));
+            e.set_zone(Some(tz));
+            e
+        };
+        // 09:00 in Tokyo is 00:00 UTC, 09:00 in New York is 14:00 UTC
+        let tokyo = zoned("Tokyo standup", 9, Tokyo);
+        let new_york = zoned("NY standup", 9, New_York);
+        // floating, read as UTC for display
+        let floating = Event::new_unchecked("Floating".into(), at(12), at(13));
+
+        let mut cal = EventCalendar::default();
+        for evt in [&tokyo, &new_york, &floating] {
+            cal.add_event(evt.clone());
+        }
+
+        let window = |from, to| {
+            cal.events_in_range_tz(
+                UTC.from_utc_datetime(&at(from)),
+                UTC.from_utc_datetime(&at(to)),
+                UTC,
+            )
+        };
+        // naively both standups start at 09:00 and would both be in range
+        let morning = window(0, 6);
+        assert_eq!(morning.len(), 1);
+        assert_eq!(morning[0].event.id(), tokyo.id());
+        assert_eq!(morning[0].start, UTC.from_utc_datetime(&at(0)));
+        assert_eq!(morning[0].end, Some(UTC.from_utc_datetime(&at(1))));
+
+        let names: Vec<_> = window(10, 20).iter().map(|e| e.event.name()).collect();
+        assert_eq!(names, vec!["Floating", "NY standup"]);
+
+        // times are shown in the display zone
+        let in_tokyo = cal.events_in_range_tz(
+            UTC.from_utc_datetime(&at(13)),
+            UTC.from_utc_datetime(&at(16)),
+            Tokyo,
+        );
+        assert_eq!(in_tokyo.len(), 1);
+        assert_eq!(in_tokyo[0].start, Tokyo.from_utc_datetime(&at(14)));
+        assert_eq!(in_tokyo[0].start.naive_local(), at(23));
+    }
+
+    #[test]
+    fn test_custom_event_store() {
+        use std::rc::Rc;
+
+        /// unordered store that records every call made to it
+        #[derive(Default)]
+        struct MockStore {
+            evts: Vec<Rc<Event>>,
+            log: Vec<String>,
+        }
+
+        impl EventStore for MockStore {
+            fn insert(&mut self, evt: Rc<Event>) -> Option<Rc<Event>> {
+                self.log.push(format!("insert {}", evt.name()));
+                let old = self.remove(evt.id());
+                self.evts.push(evt);
+                old
+            }
+
+            fn remove(&mut self, id: &Uuid) -> Option<Rc<Event>> {
+                let pos = self.evts.iter().position(|evt| evt.id() == id)?;
+                Some(self.evts.swap_remove(pos))
+            }
+
+            fn get(&self, id: &Uuid) -> Option<&Rc<Event>> {
+                self.evts.iter().find(|evt| evt.id() == id)
+            }
+
+            fn events(&self) -> Box<dyn Iterator<Item = &Rc<Event>> + '_> {
+                Box::new(self.evts.iter())
+            }
+
+            fn len(&self) -> usize {
+                self.evts.len()
+            }
+        }
+
+        let nd = first_day_2023_nd();
+        let early = Event::new("Early".into(), &nd);
+        let late = Event::new("Late".into(), &nd.with_day(9).unwrap());
+
+        // events already in the store are indexed by the calendar
+        let mut store = MockStore::default();
+        store.insert(Rc::new(late.clone()));
+        let mut cal = EventCalendar::with_store(store);
+        cal.add_event(early.clone());
+        assert!(cal.validate());
+        assert_eq!(cal.store().len(), 2);
+        assert_eq!(cal.store().log, vec!["insert Late", "insert Early"]);
+
+        // queries come back in calendar order whatever order the store uses
+        let names: Vec<_> = cal.iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["Early", "Late"]);
+        assert_eq!(cal.get(late.id()).map(|e| e.name()), Some("Late"));
+        let week = cal.events_in_range(
+            first_day_2023_ndt(),
+            nd.with_day(7).unwrap().and_time(day_end()),
+        );
+        assert_eq!(week.count(), 1);
+
+        // the store answers range queries on its own, ordered by start
+        let month_end = nd.with_day(31).unwrap().and_time(day_end());
+        let in_store: Vec<_> = cal
+            .store()
+            .range(first_day_2023_ndt(), month_end)
+            .iter()
+            .map(|e| e.name().to_string())
+            .collect();
+        assert_eq!(in_store, vec!["Early", "Late"]);
+        let second_week = nd.with_day(8).unwrap().and_time(day_start());
+        let late_only = cal.store().range(second_week, month_end);
+        assert_eq!(late_only.len(), 1);
+        assert_eq!(late_only[0].id(), late.id());
+        assert!(cal.store().range(month_end, month_end).is_empty());
+
+        assert_eq!(cal.remove_event(early.id()), Some(early.clone()));
+        assert_eq!(cal.store().len(), 1);
+        assert!(cal.validate());
+
+        // calendars with different stores can be compared and merged
+        let mut memory = EventCalendar::default();
+        memory.add_event(early.clone());
+        assert_eq!(memory.diff(&cal).added, vec![*late.id()]);
+        cal.import(memory, ImportPolicy::SkipExisting);
+        assert_eq!(cal.store().len(), 2);
+        assert!(cal.validate());
+    }
+
+    #[test]
+    fn test_minutes_overlap() {
+        let nd = first_day_2023_nd();
+        let at = |h, m, s| nd.and_hms_opt(h, m, s).unwrap();
+        let meeting = Event::new_unchecked("Meeting".into(), at(9, 0, 0), at(10, 0, 0));
+
+        let half = Event::new_unchecked("Half".into(), at(9, 30, 0), at(11, 0, 0));
+        assert_eq!(meeting.minutes_overlap(&half), 30);
+        assert_eq!(half.minutes_overlap(&meeting), 30);
+
+        let brief = Event::new_unchecked("Brief".into(), at(9, 58, 30), at(10, 30, 0));
+        assert_eq!(meeting.minutes_overlap(&brief), 1);
+
+        let later = Event::new_unchecked("Later".into(), at(10, 0, 0), at(11, 0, 0));
+        assert_eq!(meeting.minutes_overlap(&later), 0);
+
+        let all_day = Event::new("Holiday".into(), &nd);
+        assert_eq!(all_day.minutes_overlap(&meeting), 60);
+        assert_eq!(all_day.minutes_overlap(&all_day), 24 * 60);
+    }
+
+    #[test]
+    fn test_weekly_hours_by_tag() {
+        // the ISO week from Monday 2023-01-02 to Sunday 2023-01-08
+        let nd = first_day_2023_nd();
+        let at = |day: u32, hour: u32| nd.with_day(day).unwrap().and_hms_opt(hour, 0, 0).unwrap();
+        let tagged = |name: &str, start, end, tags: &[&str]| {
+            Event::new_unchecked(name.into(), start, end)
+                .with_tags(tags.iter().map(|t| t.to_string()))
+        };
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(tagged("Standup", at(2, 9), at(2, 10), &["work"]));
+        cal.add_event(tagged("Pairing", at(4, 13), at(4, 16), &["work", "dev"]));
+        cal.add_event(tagged("Gym", at(5, 18), at(5, 19), &[]));
+        // half of it falls in the next week
+        cal.add_event(tagged("Hackathon", at(8, 12), at(9, 12), &["dev"]));
+        // the previous and next weeks are not counted
+        cal.add_event(tagged("Old", at(1, 9), at(1, 17), &["work"]));
+        cal.add_event(tagged("New", at(9, 13), at(9, 17), &["work"]));
+        // counted until the end of the week
+        cal.add_event(
+            Event::new_open_ended("Trip".into(), at(7, 12)).with_tags(["travel".to_string()]),
+        );
+
+        // any day of the week gives the same report
+        let hours = cal.weekly_hours_by_tag(nd.with_day(6).unwrap());
+        assert_eq!(hours, cal.weekly_hours_by_tag(nd.with_day(2).unwrap()));
+
+        let expected: std::collections::BTreeMap<String, f64> = [
+            ("dev", 3.0 + 12.0),
+            ("travel", 36.0),
+            ("untagged", 1.0),
+            ("work", 1.0 + 3.0),
+        ]
+        .into_iter()
+        .map(|(tag, h)| (tag.to_string(), h))
+        .collect();
+        assert_eq!(hours, expected);
+    }
+
+    #[test]
+    fn test_leap_seconds() {
+        let leap = NaiveTime::from_hms_nano_opt(23, 59, 59, 1_000_000_000).unwrap();
+        assert_eq!(parse_time_loose("23:59:60"), Some(leap));
+        assert_eq!(parse_time_loose("11:59:60 pm"), Some(leap));
+        assert_eq!(
+            parse_time_loose("14:30:15"),
+            NaiveTime::from_hms_opt(14, 30, 15)
+        );
+        assert_eq!(parse_time_loose("23:59:61"), None);
+        assert_eq!(parse_time_loose("23:59:60:00"), None);
+
+        let new_year = NaiveDate::from_ymd_opt(2016, 12, 31).unwrap();
+        for (dtstart, offset) in [("20161231T235960Z", 0), ("20170101T085960+0900", 9 * 3600)] {
+            let block = format!(
+                "BEGIN:VEVENT\r\nDTSTART:{dtstart}\r\nDTEND:20170101T010000Z\r\nSUMMARY:Leap\r\nEND:VEVENT\r\n"
+            );
+            let evt = Event::from_ics_vevent(&block).unwrap();
+            assert_eq!(evt.start().second(), 59);
+            assert_eq!(evt.start().nanosecond(), 1_000_000_000);
+            assert_eq!(evt.offset().unwrap().local_minus_utc(), offset);
+            if offset == 0 {
+                assert_eq!(evt.start(), new_year.and_time(leap));
+                assert!(evt.start() > new_year.and_time(day_end()));
+                assert!(evt.to_ics_vevent().contains("DTSTART:20161231T235960Z"));
+            }
+            assert!(evt.is_valid());
+        }
+    }
+
+    #[test]
+    fn test_defragment_day() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+        let mut cal = EventCalendar::default();
+        let first = Event::new_unchecked("First".into(), at(8, 15), at(9, 0));
+        let second = Event::new_unchecked("Second".into(), at(11, 0), at(12, 30));
+        let third = Event::new_unchecked("Third".into(), at(16, 0), at(16, 20));
+        let all_day = Event::new("Holiday".into(), &nd);
+        let tomorrow = Event::new("Tomorrow".into(), &nd.succ_opt().unwrap());
+        for evt in [&third, &first, &all_day, &second, &tomorrow] {
+            cal.add_event(evt.clone());
+        }
+
+        let moves = cal.defragment_day(
+            nd,
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            chrono::Duration::minutes(10),
+        );
+        assert_eq!(
+            moves,
+            vec![
+                (*first.id(), at(9, 0)),
+                (*second.id(), at(9, 55)),
+                (*third.id(), at(11, 35))
+            ]
+        );
+
+        let day: Vec<_> = cal
+            .events_on_day(nd)
+            .into_iter()
+            .map(|e| (e.name().to_string(), e.start(), e.end()))
+            .collect();
+        assert_eq!(
+            day,
+            vec![
+                ("Holiday".to_string(), at(0, 0), nd.and_time(day_end())),
+                ("First".to_string(), at(9, 0), at(9, 45)),
+                ("Second".to_string(), at(9, 55), at(11, 25)),
+                ("Third".to_string(), at(11, 35), at(11, 55)),
+            ]
+        );
+        assert_eq!(cal.get(tomorrow.id()).unwrap().start(), tomorrow.start());
+        assert!(cal.validate());
+
+        // an already packed day is left alone
+        assert!(cal
+            .defragment_day(
+                nd,
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                chrono::Duration::minutes(10)
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn test_earlier_later() {
+        let nd = first_day_2023_nd();
+        let at = |h| nd.and_hms_opt(h, 0, 0).unwrap();
+        let morning = Event::new_unchecked("Morning".into(), at(9), at(10));
+        let evening = Event::new_unchecked("Evening".into(), at(18), at(19));
+        assert_eq!(morning.earlier(&evening).id(), morning.id());
+        assert_eq!(evening.earlier(&morning).id(), morning.id());
+        assert_eq!(morning.later(&evening).id(), evening.id());
+        assert_eq!(evening.later(&morning).id(), evening.id());
+
+        // same start, the shorter one comes first
+        let long = Event::new_unchecked("Long".into(), at(9), at(12));
+        assert_eq!(long.earlier(&morning).id(), morning.id());
+        assert_eq!(long.later(&morning).id(), long.id());
+
+        // same times, the smaller id comes first whichever way round
+        let twin = Event::new_unchecked("Twin".into(), at(9), at(10));
+        let first = morning.id().min(twin.id());
+        assert_eq!(morning.earlier(&twin).id(), first);
+        assert_eq!(twin.earlier(&morning).id(), first);
+        assert_ne!(morning.later(&twin).id(), first);
+        assert_ne!(twin.later(&morning).id(), first);
+    }
+
+    #[test]
+    fn test_import_auto() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+
+        // JSON as written by save, and a single event object
+        let mut source = EventCalendar::default();
+        source.add_event(Event::new("Saved".into(), &nd));
+        source.add_event(Event::new("Also saved".into(), &nd.succ_opt().unwrap()));
+        let mut cal = EventCalendar::default();
+        assert_eq!(
+            cal.import_auto(source.to_pretty_json().as_bytes()).unwrap(),
+            2
+        );
+        let single = serde_json::to_string(&Event::new("Single".into(), &nd)).unwrap();
+        assert_eq!(cal.import_auto(single.as_bytes()).unwrap(), 1);
+        assert_eq!(cal.iter().count(), 3);
+        // importing the same events again replaces them without adding any
+        assert_eq!(
+            cal.import_auto(source.to_pretty_json().as_bytes()).unwrap(),
+            0
+        );
+        assert_eq!(cal.iter().count(), 3);
+        // only the events new to the calendar are counted
+        source.add_event(Event::new("New".into(), &nd));
+        assert_eq!(cal.import_auto(source.to_ics().as_bytes()).unwrap(), 1);
+        assert_eq!(cal.iter().count(), 4);
+
+        // iCalendar
+        let mut cal = EventCalendar::default();
+        assert_eq!(cal.import_auto(source.to_ics().as_bytes()).unwrap(), 3);
+        let diff = cal.diff(&source);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+
+        // CSV with quoted fields, tags and columns in any order
+        let csv = "start,end,name,tags,location\n\
+                   2023-01-01T09:00,2023-01-01T09:30,Standup,work,room 1\n\
+                   \n\
+                   2023-01-01 12:00:00,2023-01-01 13:00:00,\"Lunch, with \"\"Sam\"\"\",food; social,\n";
+        let mut cal = EventCalendar::default();
+        assert_eq!(cal.import_auto(csv.as_bytes()).unwrap(), 2);
+        let evts: Vec<_> = cal.iter().collect();
+        assert_eq!(
+            (evts[0].name(), evts[0].start(), evts[0].end()),
+            ("Standup", at(9, 0), at(9, 30))
+        );
+        assert!(evts[0].has_tag("work"));
+        assert_eq!(evts[1].name(), "Lunch, with \"Sam\"");
+        assert_eq!(evts[1].tags().len(), 2);
+
+        // bad input adds nothing
+        let bad_row = "name,start,end\nOk,2023-01-01T09:00,2023-01-01T10:00\nBad,2023-01-01T11:00,2023-01-01T10:00\n";
+        assert!(matches!(
+            cal.import_auto(bad_row.as_bytes()),
+            Err(ImportError::Csv { line: 3, .. })
+        ));
+        assert!(matches!(
+            cal.import_auto("hello world".as_bytes()),
+            Err(ImportError::UnknownFormat)
+        ));
+        assert_eq!(cal.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_clamp_to_working_hours() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+        let wh = WorkingHours::new(
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        );
+        let clamp = |start, end| {
+            Event::new_unchecked("evt".into(), start, end)
+                .clamp_to_working_hours(&wh)
+                .map(|e| (e.start(), e.end()))
+        };
+
+        // inside, starting early, ending late and covering the whole window
+        assert_eq!(clamp(at(10, 0), at(11, 0)), Some((at(10, 0), at(11, 0))));
+        assert_eq!(clamp(at(7, 30), at(9, 30)), Some((at(9, 0), at(9, 30))));
+        assert_eq!(clamp(at(16, 0), at(19, 0)), Some((at(16, 0), at(17, 0))));
+        assert_eq!(clamp(at(6, 0), at(20, 0)), Some((at(9, 0), at(17, 0))));
+        let all_day = Event::new("Holiday".into(), &nd)
+            .clamp_to_working_hours(&wh)
+            .unwrap();
+        assert_eq!((all_day.start(), all_day.end()), (at(9, 0), at(17, 0)));
+        let trip = Event::new_open_ended("Trip".into(), at(12, 0))
+            .clamp_to_working_hours(&wh)
+            .unwrap();
+        assert_eq!((trip.start(), trip.end()), (at(12, 0), at(17, 0)));
+
+        // entirely before, after or touching the window
+        assert_eq!(clamp(at(6, 0), at(8, 0)), None);
+        assert_eq!(clamp(at(18, 0), at(19, 0)), None);
+        assert_eq!(clamp(at(8, 0), at(9, 0)), None);
+        assert_eq!(clamp(at(17, 0), at(18, 0)), None);
+    }
+
+    #[test]
+    fn test_next_mutual_free() {
+        let nd = first_day_2023_nd();
+        let at = |h, m| nd.and_hms_opt(h, m, 0).unwrap();
+        let busy = |periods: &[(NaiveDateTime, NaiveDateTime)]| {
+            let mut cal = EventCalendar::default();
+            for (start, end) in periods {
+                cal.add_event(Event::new_unchecked("Busy".into(), *start, *end));
+            }
+            cal
+        };
+
+        let mut store = CalendarStore::default();
+        let alice = store.add_calendar(busy(&[(at(9, 0), at(10, 0)), (at(11, 0), at(12, 0))]));
+        let bob = store.add_calendar(busy(&[(at(10, 0), at(10, 30)), (at(12, 30), at(13, 0))]));
+        let carol = store.add_calendar(busy(&[(at(10, 45), at(11, 15)), (at(12, 0), at(12, 15))]));
+        let hour = chrono::Duration::hours(1);
+        let half = chrono::Duration::minutes(30);
+
+        // the first hour everyone is free starts at 13:00
+        assert_eq!(
+            store.next_mutual_free(at(9, 0), hour, &[alice, bob, carol]),
+            Some(at(13, 0))
+        );
+        assert_eq!(
+            store.next_mutual_free(at(9, 0), half, &[alice, bob]),
+            Some(at(10, 30))
+        );
+        assert_eq!(
+            store.next_mutual_free(at(9, 0), half, &[alice, carol]),
+            Some(at(10, 0))
+        );
+        assert_eq!(
+            store.next_mutual_free(
+                at(9, 0),
+                chrono::Duration::minutes(15),
+                &[alice, bob, carol]
+            ),
+            Some(at(10, 30))
+        );
+
+        // unknown calendars are skipped
+        let unknown = Uuid::new_v4();
+        assert_eq!(
+            store.next_mutual_free(at(9, 0), hour, &[alice, unknown]),
+            Some(at(10, 0))
+        );
+        assert_eq!(
+            store.next_mutual_free(at(9, 0), hour, &[unknown]),
+            Some(at(9, 0))
+        );
+        assert_eq!(
+            store.next_mutual_free(at(9, 0), chrono::Duration::zero(), &[alice]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_reschedule_conflicts_open_ended() {
+        let nd = first_day_2023_nd();
+        let at = |h| nd.and_hms_opt(h, 0, 0).unwrap();
+        let mut cal = EventCalendar::default();
+        let trip = Event::new_open_ended("Trip".into(), at(9));
+        let meeting = Event::new_unchecked("Meeting".into(), at(10), at(11));
+        cal.add_event(trip.clone());
+        cal.add_event(meeting.clone());
+
+        // nothing can be moved past an open-ended event
+        assert!(cal
+            .reschedule_conflicts(chrono::Duration::minutes(5))
+            .is_empty());
+        assert_eq!(cal.get(meeting.id()).unwrap().start(), at(10));
+        assert!(cal.get(trip.id()).unwrap().is_open_ended());
+        assert!(cal.validate());
+
+        // an open-ended event is itself moved behind an earlier event
+        let mut cal = EventCalendar::default();
+        cal.add_event(meeting.clone());
+        let late_trip = Event::new_open_ended("Trip".into(), nd.and_hms_opt(10, 30, 0).unwrap());
+        cal.add_event(late_trip.clone());
+        let moves = cal.reschedule_conflicts(chrono::Duration::minutes(5));
+        assert_eq!(
+            moves,
+            vec![(*late_trip.id(), nd.and_hms_opt(11, 5, 0).unwrap())]
+        );
+        assert!(cal.get(late_trip.id()).unwrap().is_open_ended());
+    }
+
+    #[test]
+    fn test_open_ended_ics_round_trip() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let trip = Event::new_open_ended("Trip".into(), nd.and_hms_opt(9, 0, 0).unwrap());
+        let meeting = Event::new("Meeting".into(), &nd);
+        cal.add_event(trip.clone());
+        cal.add_event(meeting.clone());
+
+        let ics = cal.to_ics();
+        assert_eq!(ics.matches("DTEND").count(), 1);
+        let reloaded = EventCalendar::from_ics(&ics).unwrap();
+        assert_eq!(reloaded.iter().count(), 2);
+        let reloaded_trip = reloaded.get(trip.id()).unwrap();
+        assert!(reloaded_trip.is_open_ended());
+        assert_eq!(reloaded_trip.start(), trip.start());
+        assert!(!reloaded.get(meeting.id()).unwrap().is_open_ended());
+        assert!(reloaded.validate());
+    }
+
+    #[test]
+    fn test_open_ended_shift_and_range() {
+        let nd = first_day_2023_nd();
+        let at = |d, h| nd.with_day(d).unwrap().and_hms_opt(h, 0, 0).unwrap();
+        let trip = Event::new_open_ended("Trip".into(), at(2, 9));
+
+        // shifting forward keeps the end open instead of overflowing
+        let moved = trip.clone().shift(chrono::Duration::days(3));
+        assert_eq!(moved.start(), at(5, 9));
+        assert!(moved.is_open_ended());
+        let far = Event::new("Far".into(), &nd).shift(chrono::Duration::MAX);
+        assert_eq!(far.end(), NaiveDateTime::MAX);
+
+        // an open-ended event is in every window after it started
+        let mut cal = EventCalendar::default();
+        cal.add_event(trip.clone());
+        assert_eq!(cal.events_in_range_vec(at(10, 0), at(11, 0)), vec![&trip]);
+        assert!(cal.events_in_range_vec(at(1, 0), at(2, 8)).is_empty());
+        let mut seen = 0;
+        cal.for_each_in_range(at(10, 0), at(11, 0), |_| {
+            seen += 1;
+            true
+        });
+        assert_eq!(seen, 1);
+    }
+
+    #[test]
+    fn test_interval_index_sequential_ids() {
+        use crate::interval::IntervalIndex;
+
+        // sequential ids used to turn the tree into a list and overflow the stack
+        let start = first_day_2023_ndt();
+        let mut index = IntervalIndex::default();
+        let n = 100_000u128;
+        for i in 0..n {
+            let at = start + chrono::Duration::minutes(i as i64);
+            index.insert(at, at + chrono::Duration::minutes(30), Uuid::from_u128(i));
+        }
+        assert_eq!(index.len(), n as usize);
+
+        let probe = start + chrono::Duration::minutes(50_000);
+        assert_eq!(index.overlapping(probe, probe).len(), 31);
+
+        for i in 0..n {
+            let at = start + chrono::Duration::minutes(i as i64);
+            assert!(index.remove(at, Uuid::from_u128(i)));
+        }
+        assert_eq!(index.len(), 0);
+    }
+
+    #[test]
+    fn test_add_event_replaces_same_id() {
+        let nd = first_day_2023_nd();
+        let mut cal = EventCalendar::default();
+        let e = Event::new("Test".into(), &nd);
+        assert!(cal.add_event(e.clone()));
+
+        let moved = e.clone().shift(chrono::Duration::days(1));
+        assert!(!cal.add_event(moved.clone()));
+
+        assert_eq!(cal.iter().count(), 1);
+        assert_eq!(cal.iter().next(), Some(&moved));
+        assert!(cal.iter().eq(cal.iter_by_start()));
+        assert!(cal.validate());
+    }
+
+    #[test]
+    fn test_all_day_covers_midnight_in_queries() {
+        let nd = first_day_2023_nd();
+        let next = nd.with_day(2).unwrap();
+        let all_day = Event::new("All day".into(), &nd);
+        let late = Event::new_unchecked(
+            "Late".into(),
+            nd.and_hms_milli_opt(23, 59, 59, 500).unwrap(),
+            next.and_hms_opt(0, 30, 0).unwrap(),
+        );
+        assert!(all_day.overlaps(&late));
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(all_day.clone());
+        cal.add_event(late.clone());
+        assert_eq!(cal.conflicts(&late), vec![&all_day]);
+        assert_eq!(cal.overlapping_pairs().len(), 1);
+
+        // consecutive all day events leave no gap at midnight
+        let mut cal = EventCalendar::default();
+        cal.add_event(all_day.clone());
+        cal.add_event(Event::new("Next".into(), &next));
+        let window_end = NaiveDateTime::new(nd.with_day(3).unwrap(), day_start());
+        assert!(cal
+            .find_gaps(first_day_2023_ndt(), window_end, chrono::Duration::zero())
+            .is_empty());
+
+        // nothing is booked in the last second of an all day event
+        let id = cal
+            .schedule(
+                "Call".into(),
+                chrono::Duration::minutes(1),
+                nd.and_time(day_end()),
+                None,
+            )
+            .unwrap();
+        assert_eq!(cal.get(id).unwrap().start(), window_end);
+        assert!(!cal.has_conflict(cal.get(id).unwrap()));
+
+        let mut store = CalendarStore::default();
+        let busy = store.add_calendar(cal);
+        assert_eq!(
+            store.next_mutual_free(
+                nd.and_time(day_end()),
+                chrono::Duration::minutes(1),
+                &[busy]
+            ),
+            Some(window_end + chrono::Duration::minutes(1))
+        );
+
+        let joined = all_day.join(Event::new("Next".into(), &next)).unwrap();
+        assert_eq!(joined.end(), next.and_time(day_end()));
+    }
+
+    #[test]
+    fn test_event_from_ics_vevent_without_dtend() {
+        let vevent = |props: &str| format!("BEGIN:VEVENT\r\n{props}END:VEVENT\r\n");
+        let nd = first_day_2023_nd();
+
+        let e = Event::from_ics_vevent(&vevent("DTSTART:20230101T090000\r\nDURATION:PT1H30M\r\n"))
+            .unwrap();
+        assert_eq!(e.start(), nd.and_hms_opt(9, 0, 0).unwrap());
+        assert_eq!(e.end(), nd.and_hms_opt(10, 30, 0).unwrap());
+
+        let e = Event::from_ics_vevent(&vevent("DTSTART;VALUE=DATE:20230101\r\nDURATION:P2D\r\n"))
+            .unwrap();
+        assert!(e.is_all_day());
+        assert_eq!(e.end(), nd.with_day(2).unwrap().and_time(day_end()));
+
+        // a DATE start alone lasts one day
+        let e = Event::from_ics_vevent(&vevent("DTSTART;VALUE=DATE:20230101\r\n")).unwrap();
+        assert!(e.is_all_day());
+        assert_eq!(e.end(), nd.and_time(day_end()));
+
+        let e = Event::from_ics_vevent(&vevent("DTSTART:20230101T090000\r\n")).unwrap();
+        assert!(e.is_open_ended());
+
+        assert!(matches!(
+            Event::from_ics_vevent(&vevent("DTSTART:20230101T090000\r\nDURATION:1H\r\n")),
+            Err(IcsError::InvalidDuration(_))
+        ));
+        assert!(matches!(
+            Event::from_ics_vevent(&vevent("DTSTART:20230101T090000\r\nDURATION:-PT1H\r\n")),
+            Err(IcsError::InvalidEvent(_))
+        ));
+    }
+
+    #[test]
+    fn test_open_ended_round_times() {
+        let nd = first_day_2023_nd();
+        let trip = Event::new_open_ended("Trip".into(), nd.and_hms_opt(9, 7, 0).unwrap());
+
+        let rounded = trip.round_times(chrono::Duration::minutes(15)).unwrap();
+        assert_eq!(rounded.start(), nd.and_hms_opt(9, 0, 0).unwrap());
+        assert!(rounded.is_open_ended());
+    }
+
+    #[test]
+    fn test_open_ended_serialize_compact_round_trip() {
+        let trip = Event::new_open_ended("Trip".into(), first_day_2023_ndt());
+
+        let json = trip.serialize_compact();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"start":"2023-01-01T00:00:00","end":null,"name":"Trip","id":"{}"}}"#,
+                trip.id()
+            )
+        );
+        let back: Event = serde_json::from_str(&json).unwrap();
+        assert!(back.is_open_ended());
+        assert_eq!(back.start(), trip.start());
+        assert_eq!(back.id(), trip.id());
+    }
+
+    #[test]
+    fn test_recurring_ics_round_trip() {
+        use chrono::Weekday;
+
+        let nd = first_day_2023_nd();
+        let at = |d, h| nd.with_day(d).unwrap().and_hms_opt(h, 0, 0).unwrap();
+        let timed = |name: &str| {
+            Event::new(name.into(), &nd)
+                .set_start_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+                .unwrap()
+                .set_end_time(NaiveTime::from_hms_opt(10, 0, 0).unwrap())
+                .unwrap()
+        };
+
+        let mut daily = timed("Daily");
+        daily
+            .set_recurrence(Some(
+                Recurrence::new(RecurrenceRule::Daily { interval: 2 }).with_count(5),
+            ))
+            .unwrap();
+        let mut weekly = timed("Weekly");
+        weekly
+            .set_recurrence(Some(
+                Recurrence::new(RecurrenceRule::Weekly {
+                    interval: 1,
+                    by_day: vec![Weekday::Mon, Weekday::Wed],
+                })
+                .with_until(at(31, 9)),
+            ))
+            .unwrap();
+        let mut monthly = Event::new("Monthly".into(), &nd);
+        monthly
+            .set_recurrence(Some(Recurrence::new(RecurrenceRule::Monthly {
+                interval: 1,
+                by_set_pos: Some((-1, Weekday::Fri)),
+            })))
+            .unwrap();
+        let moved = Event::new_unchecked("Moved".into(), at(5, 14), at(5, 15));
+        weekly.override_occurrence(nd.with_day(4).unwrap(), moved.clone());
+
+        let mut cal = EventCalendar::default();
+        for evt in [&daily, &weekly, &monthly] {
+            cal.add_event(evt.clone());
+        }
+
+        let ics = cal.to_ics();
+        assert!(ics.contains("RRULE:FREQ=DAILY;INTERVAL=2;COUNT=5\r\n"));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20230131T090000\r\n"));
+        assert!(ics.contains("RRULE:FREQ=MONTHLY;BYDAY=-1FR\r\n"));
+        assert!(ics.contains("RECURRENCE-ID:20230104T090000\r\n"));
+
+        let imported = EventCalendar::from_ics(&ics).unwrap();
+        assert_eq!(imported.iter().count(), 3);
+        for evt in [&daily, &weekly, &monthly] {
+            let back = imported.get(evt.id()).unwrap();
+            assert_eq!(back.recurrence(), evt.recurrence());
+            assert_eq!(back.start(), evt.start());
+            assert_eq!(back.end(), evt.end());
+        }
+        let back = imported.get(weekly.id()).unwrap();
+        let occ = &back.overrides()[&nd.with_day(4).unwrap()];
+        assert_eq!((occ.name(), occ.start()), ("Moved", moved.start()));
+        assert_eq!(imported.to_ics(), ics);
+
+        // a UTC UNTIL is read in the offset of DTSTART
+        let vevent =
+            "BEGIN:VEVENT\r\nDTSTART:20230101T090000+0100\r\nDTEND:20230101T100000+0100\r\n\
+                      RRULE:FREQ=DAILY;UNTIL=20230103T080000Z\r\nEND:VEVENT\r\n";
+        let e = Event::from_ics_vevent(vevent).unwrap();
+        assert_eq!(e.recurrence().unwrap().until, Some(at(3, 9)));
+        assert!(e.to_ics_vevent().contains("UNTIL=20230103T080000Z\r\n"));
+
+        assert!(matches!(
+            Event::from_ics_vevent(&vevent.replace("FREQ=DAILY", "FREQ=YEARLY")),
+            Err(IcsError::UnsupportedRecurrence(_))
+        ));
+    }
+
+    #[test]
+    fn test_archive_survives_save_load() {
+        let nd = first_day_2023_nd();
+        let kept = Event::new("Kept".into(), &nd);
+        let old = Event::new("Old".into(), &nd.with_day(2).unwrap());
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(kept.clone());
+        cal.add_event(old.clone());
+
+        // calendars without archived events keep the plain array form
+        assert!(cal.to_pretty_json().starts_with('['));
+
+        cal.archive_event(old.id());
+        let mut buf = Vec::new();
+        cal.save(&mut buf).unwrap();
+        for json in [buf, cal.to_pretty_json().into_bytes()] {
+            let loaded = EventCalendar::load(json.as_slice()).unwrap();
+            assert_eq!(loaded.iter().collect::<Vec<_>>(), vec![&kept]);
+            assert_eq!(loaded.archived_events().collect::<Vec<_>>(), vec![&old]);
+            assert!(loaded.validate());
+        }
+    }
+
+    #[cfg(feature = "binary")]
+    #[test]
+    fn test_archive_survives_binary_round_trip() {
+        let nd = first_day_2023_nd();
+        let kept = Event::new("Kept".into(), &nd);
+        let old = Event::new("Old".into(), &nd.with_day(2).unwrap());
+
+        let mut cal = EventCalendar::default();
+        cal.add_event(kept.clone());
+        cal.add_event(old.clone());
+        let without_archive = cal.to_bytes();
+        cal.archive_event(old.id());
+
+        let decoded = EventCalendar::from_bytes(&cal.to_bytes()).unwrap();
+        assert_eq!(decoded.iter().collect::<Vec<_>>(), vec![&kept]);
+        assert_eq!(decoded.archived_events().collect::<Vec<_>>(), vec![&old]);
+
+        // bytes encoded before archiving was persisted still decode
+        let decoded = EventCalendar::from_bytes(&without_archive).unwrap();
+        assert_eq!(decoded.iter().count(), 2);
+        assert_eq!(decoded.archived_events().count(), 0);
+    }
+
+    #[test]
+    fn test_invalid_property_keys_rejected() {
+        let mut e = Event::new("A".into(), &first_day_2023_nd());
+        assert!(matches!(
+            e.set_property("room;code".into(), "B12".into()),
+            Err(EventError::InvalidPropertyKey(_))
+        ));
+        assert!(matches!(
+            e.set_property("".into(), "B12".into()),
+            Err(EventError::InvalidPropertyKey(_))
+        ));
+        assert!(e.properties().is_empty());
+
+        // a key that would break the X- name on export can't be loaded either
+        e.set_property("room".into(), "B12".into()).unwrap();
+        let json = e.serialize().replace("\"room\"", "\"room:1\"");
+        assert!(serde_json::from_str::<Event>(&json).is_err());
+    }
+
+    #[test]
+    fn test_with_store_invalid_event_does_not_panic() {
+        let start = first_day_2023_ndt();
+        let backwards = Event::new_unchecked(
+            "Backwards".into(),
+            start,
+            start - chrono::Duration::hours(1),
+        );
+        let mut store = MemoryStore::default();
+        store.insert(std::rc::Rc::new(backwards));
+
+        // the event is indexed as it is and reported by validate
+        let mut cal = EventCalendar::with_store(store);
+        assert_eq!(cal.store().len(), 1);
+        assert!(!cal.validate());
+
+        cal.compact();
+        assert!(!cal.validate());
     }
 }
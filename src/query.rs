@@ -0,0 +1,92 @@
+use chrono::NaiveDateTime;
+
+use super::{cal::EventCalendar, event::Event};
+
+/// A set of filters over the events of an [`EventCalendar`], combined with
+/// AND and applied in chronological order by [`Query::collect`]
+///
+/// # Examples
+/// ```
+/// use calib::{Event, EventCalendar, Query};
+/// use chrono::NaiveDate;
+///
+/// let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+/// let mut event = Event::new("Standup".into(), &date);
+/// event.add_tag("work".into());
+///
+/// let mut cal = EventCalendar::default();
+/// cal.add_event(event);
+/// cal.add_event(Event::new("Gym".into(), &date));
+///
+/// let work = Query::new().with_tag("work").limit(5).collect(&cal);
+/// assert_eq!(work.len(), 1);
+/// assert_eq!(work[0].name(), "Standup");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Query {
+    range: Option<(NaiveDateTime, NaiveDateTime)>,
+    tags: Vec<String>,
+    name: Option<String>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    /// create a query matching every event
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// only match events between start and end, as with
+    /// [`EventCalendar::events_in_range`]
+    pub fn in_range(self, start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        Self {
+            range: Some((start, end)),
+            ..self
+        }
+    }
+
+    /// only match events with the given tag, can be chained to require
+    /// several tags
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// only match events whose name contains `name`
+    pub fn named(self, name: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    /// return at most `limit` events
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// returns true if the event passes every filter except the range
+    fn matches(&self, evt: &Event) -> bool {
+        self.tags.iter().all(|tag| evt.has_tag(tag))
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| evt.name().contains(name.as_str()))
+    }
+
+    /// run the query against `cal`, returning matching events in
+    /// chronological order
+    pub fn collect<'a>(&self, cal: &'a EventCalendar) -> Vec<&'a Event> {
+        let evts: Box<dyn Iterator<Item = &'a Event>> = match self.range {
+            Some((start, end)) => Box::new(cal.events_in_range(start, end).map(AsRef::as_ref)),
+            None => Box::new(cal.iter()),
+        };
+
+        evts.filter(|evt| self.matches(evt))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
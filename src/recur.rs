@@ -0,0 +1,229 @@
+use chrono::{Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, Weekday};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
+
+/// the most occurrences [`crate::Event::occurrences_between`] will expand
+/// at once
+pub const MAX_OCCURRENCES: usize = 10_000;
+
+/// the `pos`-th `weekday` of the month starting at `month`, counting from
+/// the end of the month if `pos` is negative
+fn nth_weekday_of_month(month: NaiveDate, pos: i8, weekday: Weekday) -> Option<NaiveDate> {
+    let weeks = u64::from(pos.unsigned_abs()).checked_sub(1)?;
+    let date = if pos > 0 {
+        let offset =
+            (7 + weekday.num_days_from_monday() - month.weekday().num_days_from_monday()) % 7;
+        month.checked_add_days(Days::new(u64::from(offset) + 7 * weeks))?
+    } else {
+        let last = month.checked_add_months(Months::new(1))?.pred_opt()?;
+        let offset =
+            (7 + last.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+        last.checked_sub_days(Days::new(u64::from(offset) + 7 * weeks))?
+    };
+
+    (date.month() == month.month()).then_some(date)
+}
+
+/// returns true if `pos` can pick a weekday within a month, i.e. it counts
+/// at most five weeks from either end and is not zero
+pub(crate) fn is_valid_set_pos(pos: i8) -> bool {
+    matches!(pos, -5..=-1 | 1..=5)
+}
+
+/// deserialize `by_set_pos`, rejecting positions no month can have
+fn set_pos<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<(i8, Weekday)>, D::Error> {
+    match Option::<(i8, Weekday)>::deserialize(deserializer)? {
+        Some((pos, _)) if !is_valid_set_pos(pos) => Err(serde::de::Error::custom(
+            crate::EventError::InvalidSetPos(pos),
+        )),
+        by_set_pos => Ok(by_set_pos),
+    }
+}
+
+/// How often a recurring event repeats, every `interval` days, weeks or months
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub enum RecurrenceRule {
+    Daily {
+        interval: u32,
+    },
+    /// repeats on each of the `by_day` weekdays (e.g. Mon/Wed/Fri), or on
+    /// the weekday of the first occurrence if `by_day` is empty
+    Weekly {
+        interval: u32,
+        by_day: Vec<Weekday>,
+    },
+    /// repeats on the day of the month of the first occurrence, or if
+    /// `by_set_pos` is set on the nth weekday of the month (e.g. `(3,
+    /// Thu)` for the 3rd Thursday), counting from the end for negative n
+    /// (e.g. `(-1, Fri)` for the last Friday). n must be within `1..=5` or
+    /// `-5..=-1`
+    Monthly {
+        interval: u32,
+        #[serde(default, deserialize_with = "set_pos")]
+        by_set_pos: Option<(i8, Weekday)>,
+    },
+}
+
+impl RecurrenceRule {
+    /// key used for ordering, since Weekday does not implement Ord
+    fn sort_key(&self) -> (u8, u32, Vec<u32>) {
+        match self {
+            RecurrenceRule::Daily { interval } => (0, *interval, Vec::new()),
+            RecurrenceRule::Weekly { interval, by_day } => (
+                1,
+                *interval,
+                by_day.iter().map(Weekday::num_days_from_monday).collect(),
+            ),
+            RecurrenceRule::Monthly {
+                interval,
+                by_set_pos,
+            } => (
+                2,
+                *interval,
+                by_set_pos
+                    .iter()
+                    .flat_map(|(pos, day)| [*pos as u32, day.num_days_from_monday()])
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl PartialOrd for RecurrenceRule {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RecurrenceRule {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+/// Describes how an event repeats and when the repetition stops
+///
+/// # Examples
+/// ```
+/// use calib::{Recurrence, RecurrenceRule};
+///
+/// // every other week, ten times in total
+/// let rule = RecurrenceRule::Weekly { interval: 2, by_day: vec![] };
+/// let rec = Recurrence::new(rule).with_count(10);
+/// assert_eq!(rec.count, Some(10));
+/// ```
+#[derive(PartialOrd, Ord, PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct Recurrence {
+    pub rule: RecurrenceRule,
+    /// total number of occurrences, including the first one
+    pub count: Option<u32>,
+    /// no occurrence starts after this time
+    pub until: Option<NaiveDateTime>,
+}
+
+impl Recurrence {
+    /// create a recurrence that repeats forever
+    pub fn new(rule: RecurrenceRule) -> Self {
+        Self {
+            rule,
+            count: None,
+            until: None,
+        }
+    }
+
+    /// limit the recurrence to `count` occurrences
+    pub fn with_count(self, count: u32) -> Self {
+        Self {
+            count: Some(count),
+            ..self
+        }
+    }
+
+    /// stop the recurrence after `until`
+    pub fn with_until(self, until: NaiveDateTime) -> Self {
+        Self {
+            until: Some(until),
+            ..self
+        }
+    }
+
+    /// returns an iterator over the start times of all occurrences of an
+    /// event first starting at `first`, in chronological order. Monthly
+    /// occurrences are skipped in months that lack the starting day
+    pub(crate) fn starts(&self, first: NaiveDateTime) -> impl Iterator<Item = NaiveDateTime> + '_ {
+        (0u32..)
+            .map(move |k| self.period_starts(first, k))
+            // stop once the dates can no longer be represented
+            .take_while(|starts| starts.is_some())
+            .flatten()
+            .flatten()
+            // weekly periods begin on Monday, which may be before the first start
+            .filter(move |start| *start >= first)
+            .take_while(move |start| self.until.is_none_or(|until| *start <= until))
+            .take(self.count.map_or(usize::MAX, |count| count as usize))
+    }
+
+    /// sorted start times within the k-th period after `first`, None once
+    /// the dates overflow. A period may have no starts, e.g. a month that
+    /// lacks the starting day
+    fn period_starts(&self, first: NaiveDateTime, k: u32) -> Option<Vec<NaiveDateTime>> {
+        match &self.rule {
+            RecurrenceRule::Daily { interval } => {
+                let days = i64::from(k) * i64::from((*interval).max(1));
+                first
+                    .checked_add_signed(Duration::days(days))
+                    .map(|start| vec![start])
+            }
+            RecurrenceRule::Weekly { interval, by_day } => {
+                let weeks = i64::from(k) * i64::from((*interval).max(1));
+                let week_start = first.checked_add_signed(Duration::weeks(weeks))?;
+                if by_day.is_empty() {
+                    return Some(vec![week_start]);
+                }
+
+                let monday =
+                    week_start - Duration::days(week_start.weekday().num_days_from_monday().into());
+                let mut offsets: Vec<u32> =
+                    by_day.iter().map(Weekday::num_days_from_monday).collect();
+                offsets.sort_unstable();
+                offsets.dedup();
+
+                offsets
+                    .into_iter()
+                    .map(|offset| monday.checked_add_signed(Duration::days(offset.into())))
+                    .collect()
+            }
+            RecurrenceRule::Monthly {
+                interval,
+                by_set_pos: Some((pos, weekday)),
+            } => {
+                let months = k.checked_mul((*interval).max(1))?;
+                let month = first
+                    .date()
+                    .with_day(1)?
+                    .checked_add_months(Months::new(months))?;
+                // months lacking e.g. a 5th Thursday have no start
+                Some(
+                    nth_weekday_of_month(month, *pos, *weekday)
+                        .map(|date| date.and_time(first.time()))
+                        .into_iter()
+                        .collect(),
+                )
+            }
+            RecurrenceRule::Monthly {
+                interval,
+                by_set_pos: None,
+            } => {
+                let months = k.checked_mul((*interval).max(1))?;
+                let start = first.checked_add_months(Months::new(months))?;
+                // chrono clamps to the end of shorter months, skip those instead
+                Some(
+                    (start.day() == first.day())
+                        .then_some(start)
+                        .into_iter()
+                        .collect(),
+                )
+            }
+        }
+    }
+}
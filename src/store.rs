@@ -0,0 +1,107 @@
+use chrono::{Duration, NaiveDateTime};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use super::{EventCalendar, IntoUuid, StoreError};
+
+/// A collection of calendars identified by id, for apps that manage several
+/// calendars (e.g. work and personal) at once
+#[derive(Default)]
+pub struct CalendarStore {
+    calendars: BTreeMap<Uuid, EventCalendar>,
+}
+
+impl CalendarStore {
+    /// add a calendar to the store, returning the id it was stored under
+    pub fn add_calendar(&mut self, calendar: EventCalendar) -> Uuid {
+        let id = Uuid::new_v4();
+        self.calendars.insert(id, calendar);
+        id
+    }
+
+    /// return a reference to a calendar from its id
+    pub fn get<T: IntoUuid>(&self, id: T) -> Option<&EventCalendar> {
+        self.calendars.get(&id.into_uuid())
+    }
+
+    /// return a mutable reference to a calendar from its id
+    pub fn get_mut<T: IntoUuid>(&mut self, id: T) -> Option<&mut EventCalendar> {
+        self.calendars.get_mut(&id.into_uuid())
+    }
+
+    /// remove a calendar and all of its events from the store
+    pub fn remove_calendar<T: IntoUuid>(&mut self, id: T) -> Option<EventCalendar> {
+        self.calendars.remove(&id.into_uuid())
+    }
+
+    /// return the earliest time at or after `after` at which all of the
+    /// given calendars are free for `duration`, e.g. to find a meeting time
+    /// for several people. Ids not in the store are ignored, so they never
+    /// block a slot. None if the duration is not positive or no slot exists
+    pub fn next_mutual_free(
+        &self,
+        after: NaiveDateTime,
+        duration: Duration,
+        calendar_ids: &[Uuid],
+    ) -> Option<NaiveDateTime> {
+        if duration <= Duration::zero() {
+            return None;
+        }
+
+        let calendars: Vec<&EventCalendar> = calendar_ids
+            .iter()
+            .filter_map(|id| self.calendars.get(id))
+            .collect();
+        let mut candidate = after;
+
+        // each calendar pushes the candidate to its next free slot, until
+        // a candidate is free in all of them
+        loop {
+            let mut latest = candidate;
+            for cal in &calendars {
+                latest = latest.max(cal.next_free_slot(duration, candidate, None)?);
+            }
+
+            if latest == candidate {
+                return Some(candidate);
+            }
+            candidate = latest;
+        }
+    }
+
+    /// move an event from one calendar to another. Both calendars and the
+    /// event are checked before anything changes, so on error neither
+    /// calendar is modified
+    pub fn move_event(
+        &mut self,
+        from: Uuid,
+        to: Uuid,
+        event_id: impl IntoUuid,
+    ) -> Result<(), StoreError> {
+        let event_id = event_id.into_uuid();
+
+        if !self.calendars.contains_key(&to) {
+            return Err(StoreError::CalendarNotFound(to));
+        }
+
+        let source = self
+            .calendars
+            .get_mut(&from)
+            .ok_or(StoreError::CalendarNotFound(from))?;
+
+        if from == to {
+            return match source.get(event_id) {
+                Some(_) => Ok(()),
+                None => Err(StoreError::EventNotFound(event_id)),
+            };
+        }
+
+        let evt = source
+            .remove_event(event_id)
+            .ok_or(StoreError::EventNotFound(event_id))?;
+
+        // checked above that the destination exists
+        self.calendars.get_mut(&to).unwrap().add_event(evt);
+        Ok(())
+    }
+}